@@ -0,0 +1,361 @@
+//! Reader and writer for COLMAP's binary model format.
+//!
+//! See <https://colmap.github.io/format.html#binary-file-format> for the layout of
+//! `cameras.bin`, `images.bin` and `points3D.bin`. All values are little-endian.
+
+use super::{Camera, CameraModel, Image, Point2D, Point3D, Reconstruction, TrackElement};
+use anyhow::{anyhow, Context, Result};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// COLMAP's marker for a keypoint without a 3D point.
+const INVALID_POINT3D_ID: u64 = u64::MAX;
+
+/// Returns whether a directory contains a binary model.
+pub fn exists(dir: &Path) -> bool {
+    ["cameras.bin", "images.bin", "points3D.bin"]
+        .iter()
+        .all(|name| dir.join(name).exists())
+}
+
+/// Reads `cameras.bin`, `images.bin` and `points3D.bin` from a directory.
+pub fn read_model(dir: &Path) -> Result<Reconstruction> {
+    let cameras = read_file(&dir.join("cameras.bin"), read_cameras)?;
+    let images = read_file(&dir.join("images.bin"), read_images)?;
+    let points3d = read_file(&dir.join("points3D.bin"), read_points3d)?;
+    Ok(Reconstruction {
+        cameras,
+        images,
+        points3d,
+    })
+}
+
+/// Writes `cameras.bin`, `images.bin` and `points3D.bin` into a directory.
+pub fn write_model(dir: &Path, model: &Reconstruction) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    write_file(&dir.join("cameras.bin"), |w| write_cameras(w, model))?;
+    write_file(&dir.join("images.bin"), |w| write_images(w, model))?;
+    write_file(&dir.join("points3D.bin"), |w| write_points3d(w, model))?;
+    Ok(())
+}
+
+fn read_file<T>(path: &Path, read: impl FnOnce(&mut BufReader<File>) -> Result<T>) -> Result<T> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    read(&mut BufReader::new(file)).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+fn write_file(path: &Path, write: impl FnOnce(&mut BufWriter<File>) -> Result<()>) -> Result<()> {
+    let file =
+        File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    write(&mut writer).with_context(|| format!("Failed to write {}", path.display()))?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn read_u8(r: &mut impl Read) -> Result<u8> {
+    let mut buf = [0; 1];
+    r.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_i32(r: &mut impl Read) -> Result<i32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(i32::from_le_bytes(buf))
+}
+
+fn read_u32(r: &mut impl Read) -> Result<u32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(r: &mut impl Read) -> Result<u64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_f64(r: &mut impl Read) -> Result<f64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(f64::from_le_bytes(buf))
+}
+
+fn read_f64s<const N: usize>(r: &mut impl Read) -> Result<[f64; N]> {
+    let mut values = [0.0; N];
+    for v in &mut values {
+        *v = read_f64(r)?;
+    }
+    Ok(values)
+}
+
+/// Reads a null-terminated string.
+fn read_string(r: &mut impl Read) -> Result<String> {
+    let mut bytes = Vec::new();
+    loop {
+        match read_u8(r)? {
+            0 => break,
+            b => bytes.push(b),
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| anyhow!("image name is not valid UTF-8"))
+}
+
+fn read_cameras(r: &mut impl Read) -> Result<BTreeMap<u32, Camera>> {
+    let count = read_u64(r)?;
+    let mut cameras = BTreeMap::new();
+    for _ in 0..count {
+        let id = read_u32(r)?;
+        let model_id = read_i32(r)?;
+        let model = usize::try_from(model_id)
+            .ok()
+            .and_then(|i| CameraModel::ALL.get(i).copied())
+            .ok_or_else(|| anyhow!("Unknown camera model id {} of camera {}", model_id, id))?;
+        let width = read_u64(r)?;
+        let height = read_u64(r)?;
+        let params = (0..model.num_params())
+            .map(|_| read_f64(r))
+            .collect::<Result<Vec<_>>>()?;
+        cameras.insert(
+            id,
+            Camera {
+                id,
+                model,
+                width,
+                height,
+                params,
+            },
+        );
+    }
+    Ok(cameras)
+}
+
+fn read_images(r: &mut impl Read) -> Result<BTreeMap<u32, Image>> {
+    let count = read_u64(r)?;
+    let mut images = BTreeMap::new();
+    for _ in 0..count {
+        let id = read_u32(r)?;
+        let qvec = read_f64s::<4>(r)?;
+        let tvec = read_f64s::<3>(r)?;
+        let camera_id = read_u32(r)?;
+        let name = read_string(r)?;
+        let num_points2d = read_u64(r)?;
+        let points2d = (0..num_points2d)
+            .map(|_| {
+                let xy = read_f64s::<2>(r)?;
+                let point3d_id = read_u64(r)?;
+                Ok(Point2D {
+                    xy,
+                    point3d_id: (point3d_id != INVALID_POINT3D_ID).then_some(point3d_id),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        images.insert(
+            id,
+            Image {
+                id,
+                qvec,
+                tvec,
+                camera_id,
+                name,
+                points2d,
+            },
+        );
+    }
+    Ok(images)
+}
+
+fn read_points3d(r: &mut impl Read) -> Result<BTreeMap<u64, Point3D>> {
+    let count = read_u64(r)?;
+    let mut points = BTreeMap::new();
+    for _ in 0..count {
+        let id = read_u64(r)?;
+        let xyz = read_f64s::<3>(r)?;
+        let rgb = [read_u8(r)?, read_u8(r)?, read_u8(r)?];
+        let error = read_f64(r)?;
+        let track_length = read_u64(r)?;
+        let track = (0..track_length)
+            .map(|_| {
+                Ok(TrackElement {
+                    image_id: read_u32(r)?,
+                    point2d_idx: read_u32(r)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        points.insert(
+            id,
+            Point3D {
+                id,
+                xyz,
+                rgb,
+                error,
+                track,
+            },
+        );
+    }
+    Ok(points)
+}
+
+fn write_f64s(w: &mut impl Write, values: &[f64]) -> Result<()> {
+    for v in values {
+        w.write_all(&v.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn write_cameras(w: &mut impl Write, model: &Reconstruction) -> Result<()> {
+    w.write_all(&(model.cameras.len() as u64).to_le_bytes())?;
+    for camera in model.cameras.values() {
+        let model_id = CameraModel::ALL
+            .iter()
+            .position(|&m| m == camera.model)
+            .ok_or_else(|| anyhow!("Camera {} has no binary model id", camera.id))?
+            as i32;
+        w.write_all(&camera.id.to_le_bytes())?;
+        w.write_all(&model_id.to_le_bytes())?;
+        w.write_all(&camera.width.to_le_bytes())?;
+        w.write_all(&camera.height.to_le_bytes())?;
+        write_f64s(w, &camera.params)?;
+    }
+    Ok(())
+}
+
+fn write_images(w: &mut impl Write, model: &Reconstruction) -> Result<()> {
+    w.write_all(&(model.images.len() as u64).to_le_bytes())?;
+    for image in model.images.values() {
+        w.write_all(&image.id.to_le_bytes())?;
+        write_f64s(w, &image.qvec)?;
+        write_f64s(w, &image.tvec)?;
+        w.write_all(&image.camera_id.to_le_bytes())?;
+        w.write_all(image.name.as_bytes())?;
+        w.write_all(&[0])?;
+        w.write_all(&(image.points2d.len() as u64).to_le_bytes())?;
+        for point in &image.points2d {
+            write_f64s(w, &point.xy)?;
+            let id = point.point3d_id.unwrap_or(INVALID_POINT3D_ID);
+            w.write_all(&id.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+fn write_points3d(w: &mut impl Write, model: &Reconstruction) -> Result<()> {
+    w.write_all(&(model.points3d.len() as u64).to_le_bytes())?;
+    for point in model.points3d.values() {
+        w.write_all(&point.id.to_le_bytes())?;
+        write_f64s(w, &point.xyz)?;
+        w.write_all(&point.rgb)?;
+        w.write_all(&point.error.to_le_bytes())?;
+        w.write_all(&(point.track.len() as u64).to_le_bytes())?;
+        for element in &point.track {
+            w.write_all(&element.image_id.to_le_bytes())?;
+            w.write_all(&element.point2d_idx.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colmap::{Camera, Image, Point2D};
+
+    fn sample_model() -> Reconstruction {
+        let mut model = Reconstruction::default();
+        model.cameras.insert(
+            1,
+            Camera {
+                id: 1,
+                model: CameraModel::OpenCV,
+                width: 1920,
+                height: 1080,
+                params: vec![1500.0, 1510.0, 960.0, 540.0, 0.01, -0.02, 0.001, 0.002],
+            },
+        );
+        model.images.insert(
+            3,
+            Image {
+                id: 3,
+                qvec: [0.5, 0.5, -0.5, 0.5],
+                tvec: [1.0, -2.0, 3.5],
+                camera_id: 1,
+                name: "input/frame_000003.jpg".to_string(),
+                points2d: vec![
+                    Point2D {
+                        xy: [10.25, 20.75],
+                        point3d_id: Some(42),
+                    },
+                    // Stored as POINT3D_ID = -1.
+                    Point2D {
+                        xy: [0.0, 1079.5],
+                        point3d_id: None,
+                    },
+                ],
+            },
+        );
+        model.images.insert(
+            4,
+            Image {
+                id: 4,
+                qvec: [1.0, 0.0, 0.0, 0.0],
+                tvec: [0.0; 3],
+                camera_id: 1,
+                name: "frame_000004.jpg".to_string(),
+                points2d: Vec::new(),
+            },
+        );
+        model.points3d.insert(
+            42,
+            Point3D {
+                id: 42,
+                xyz: [0.1, -0.2, 5.0],
+                rgb: [12, 34, 56],
+                error: 0.75,
+                track: vec![TrackElement {
+                    image_id: 3,
+                    point2d_idx: 0,
+                }],
+            },
+        );
+        model.points3d.insert(
+            43,
+            Point3D {
+                id: 43,
+                xyz: [1.0, 1.0, 1.0],
+                rgb: [255, 255, 255],
+                error: 0.0,
+                track: Vec::new(),
+            },
+        );
+        model
+    }
+
+    #[test]
+    fn write_then_read_gives_the_same_model() {
+        let dir = std::env::temp_dir().join(format!("polyfjord3d-binary-{}", std::process::id()));
+        let model = sample_model();
+        write_model(&dir, &model).unwrap();
+        assert!(exists(&dir));
+
+        let read = read_model(&dir).unwrap();
+        assert_eq!(read, model);
+        assert_eq!(read.images[&3].points2d[1].point3d_id, None);
+        assert!(read.points3d[&43].track.is_empty());
+
+        // The keypoint without a 3D point is written as -1.
+        let images = fs::read(dir.join("images.bin")).unwrap();
+        // Count, image id, qvec, tvec and camera id precede the name.
+        let name_end = 8 + 4 + 32 + 24 + 4 + "input/frame_000003.jpg".len();
+        let second_id = name_end + 1 + 8 + (16 + 8) + 16;
+        assert_eq!(
+            i64::from_le_bytes(images[second_id..second_id + 8].try_into().unwrap()),
+            -1
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,343 @@
+//! Typed representation of COLMAP sparse models.
+//!
+//! A sparse model consists of cameras (intrinsics), images (poses and 2D keypoints)
+//! and 3D points (positions, colors and tracks). The [`text`] and [`binary`] modules
+//! read and write the two on-disk formats, so models can be inspected and converted
+//! without `colmap model_converter`.
+
+pub mod binary;
+pub mod text;
+
+use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Reads a model from a directory, preferring the binary format if both exist.
+pub fn read_model(dir: &Path) -> Result<Reconstruction> {
+    if binary::exists(dir) {
+        binary::read_model(dir)
+    } else if text::exists(dir) {
+        text::read_model(dir)
+    } else {
+        Err(anyhow!("No COLMAP model found in {}", dir.display()))
+    }
+}
+
+/// Returns the sub-model directories (`0`, `1`, ...) the mapper created in
+/// `sparse_dir`, in numerical order. Directories without a model are skipped.
+pub fn sub_model_dirs(sparse_dir: &Path) -> Result<Vec<PathBuf>> {
+    if !sparse_dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut dirs = Vec::new();
+    for entry in fs::read_dir(sparse_dir)? {
+        let path = entry?.path();
+        let index = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.parse::<u32>().ok());
+        if let Some(index) = index {
+            if path.is_dir() && (binary::exists(&path) || text::exists(&path)) {
+                dirs.push((index, path));
+            }
+        }
+    }
+    dirs.sort();
+    Ok(dirs.into_iter().map(|(_, path)| path).collect())
+}
+
+/// The camera models supported by COLMAP.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CameraModel {
+    SimplePinhole,
+    Pinhole,
+    SimpleRadial,
+    Radial,
+    OpenCV,
+    OpenCVFisheye,
+    FullOpenCV,
+    Fov,
+    SimpleRadialFisheye,
+    RadialFisheye,
+    ThinPrismFisheye,
+    RadTanThinPrismFisheye,
+}
+
+impl CameraModel {
+    /// All models, indexed by their COLMAP model id.
+    pub const ALL: [CameraModel; 12] = [
+        CameraModel::SimplePinhole,
+        CameraModel::Pinhole,
+        CameraModel::SimpleRadial,
+        CameraModel::Radial,
+        CameraModel::OpenCV,
+        CameraModel::OpenCVFisheye,
+        CameraModel::FullOpenCV,
+        CameraModel::Fov,
+        CameraModel::SimpleRadialFisheye,
+        CameraModel::RadialFisheye,
+        CameraModel::ThinPrismFisheye,
+        CameraModel::RadTanThinPrismFisheye,
+    ];
+
+    /// The name COLMAP uses for the model in TXT files.
+    pub fn name(self) -> &'static str {
+        match self {
+            CameraModel::SimplePinhole => "SIMPLE_PINHOLE",
+            CameraModel::Pinhole => "PINHOLE",
+            CameraModel::SimpleRadial => "SIMPLE_RADIAL",
+            CameraModel::Radial => "RADIAL",
+            CameraModel::OpenCV => "OPENCV",
+            CameraModel::OpenCVFisheye => "OPENCV_FISHEYE",
+            CameraModel::FullOpenCV => "FULL_OPENCV",
+            CameraModel::Fov => "FOV",
+            CameraModel::SimpleRadialFisheye => "SIMPLE_RADIAL_FISHEYE",
+            CameraModel::RadialFisheye => "RADIAL_FISHEYE",
+            CameraModel::ThinPrismFisheye => "THIN_PRISM_FISHEYE",
+            CameraModel::RadTanThinPrismFisheye => "RAD_TAN_THIN_PRISM_FISHEYE",
+        }
+    }
+
+    /// The number of parameters of the model.
+    pub fn num_params(self) -> usize {
+        match self {
+            CameraModel::SimplePinhole => 3,
+            CameraModel::Pinhole => 4,
+            CameraModel::SimpleRadial => 4,
+            CameraModel::Radial => 5,
+            CameraModel::OpenCV => 8,
+            CameraModel::OpenCVFisheye => 8,
+            CameraModel::FullOpenCV => 12,
+            CameraModel::Fov => 5,
+            CameraModel::SimpleRadialFisheye => 4,
+            CameraModel::RadialFisheye => 5,
+            CameraModel::ThinPrismFisheye => 12,
+            CameraModel::RadTanThinPrismFisheye => 16,
+        }
+    }
+
+    /// Whether the model has a single focal length shared by both axes.
+    fn has_single_focal_length(self) -> bool {
+        matches!(
+            self,
+            CameraModel::SimplePinhole
+                | CameraModel::SimpleRadial
+                | CameraModel::Radial
+                | CameraModel::SimpleRadialFisheye
+                | CameraModel::RadialFisheye
+        )
+    }
+}
+
+impl FromStr for CameraModel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        CameraModel::ALL
+            .into_iter()
+            .find(|model| model.name() == s)
+            .ok_or_else(|| anyhow!("Unknown camera model: {}", s))
+    }
+}
+
+impl fmt::Display for CameraModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Intrinsics of a camera.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Camera {
+    pub id: u32,
+    pub model: CameraModel,
+    pub width: u64,
+    pub height: u64,
+    /// Model parameters in COLMAP order, starting with the focal length(s) and the
+    /// principal point.
+    pub params: Vec<f64>,
+}
+
+impl Camera {
+    /// Focal lengths `(fx, fy)` in pixels.
+    pub fn focal_length(&self) -> (f64, f64) {
+        if self.model.has_single_focal_length() {
+            (self.params[0], self.params[0])
+        } else {
+            (self.params[0], self.params[1])
+        }
+    }
+
+    /// Principal point `(cx, cy)` in pixels.
+    pub fn principal_point(&self) -> (f64, f64) {
+        if self.model.has_single_focal_length() {
+            (self.params[1], self.params[2])
+        } else {
+            (self.params[2], self.params[3])
+        }
+    }
+
+    /// Distortion parameters that follow the focal length and principal point.
+    pub fn distortion(&self) -> &[f64] {
+        let offset = if self.model.has_single_focal_length() {
+            3
+        } else {
+            4
+        };
+        &self.params[offset.min(self.params.len())..]
+    }
+}
+
+/// A 2D keypoint of an image, possibly observing a 3D point.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Point2D {
+    pub xy: [f64; 2],
+    pub point3d_id: Option<u64>,
+}
+
+/// A registered image with its pose.
+///
+/// The pose maps world to camera coordinates: `x_cam = R(qvec) * x_world + tvec`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub id: u32,
+    /// Rotation quaternion `(w, x, y, z)`.
+    pub qvec: [f64; 4],
+    pub tvec: [f64; 3],
+    pub camera_id: u32,
+    pub name: String,
+    pub points2d: Vec<Point2D>,
+}
+
+impl Image {
+    /// The world-to-camera rotation matrix, row-major.
+    pub fn rotation_matrix(&self) -> [[f64; 3]; 3] {
+        let [w, x, y, z] = self.qvec;
+        [
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+            ],
+        ]
+    }
+
+    /// The position of the camera center in world coordinates, `-R^T * t`.
+    pub fn camera_center(&self) -> [f64; 3] {
+        let r = self.rotation_matrix();
+        let t = self.tvec;
+        let mut center = [0.0; 3];
+        for (i, c) in center.iter_mut().enumerate() {
+            *c = -(r[0][i] * t[0] + r[1][i] * t[1] + r[2][i] * t[2]);
+        }
+        center
+    }
+
+    /// The number of keypoints that observe a 3D point.
+    pub fn num_points3d(&self) -> usize {
+        self.points2d
+            .iter()
+            .filter(|p| p.point3d_id.is_some())
+            .count()
+    }
+}
+
+/// One observation of a 3D point in an image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TrackElement {
+    pub image_id: u32,
+    pub point2d_idx: u32,
+}
+
+/// A triangulated 3D point.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Point3D {
+    pub id: u64,
+    pub xyz: [f64; 3],
+    pub rgb: [u8; 3],
+    /// Mean reprojection error in pixels.
+    pub error: f64,
+    pub track: Vec<TrackElement>,
+}
+
+/// A complete sparse model.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Reconstruction {
+    pub cameras: BTreeMap<u32, Camera>,
+    pub images: BTreeMap<u32, Image>,
+    pub points3d: BTreeMap<u64, Point3D>,
+}
+
+impl Reconstruction {
+    /// Mean number of images observing each 3D point.
+    pub fn mean_track_length(&self) -> f64 {
+        if self.points3d.is_empty() {
+            return 0.0;
+        }
+        let total: usize = self.points3d.values().map(|p| p.track.len()).sum();
+        total as f64 / self.points3d.len() as f64
+    }
+
+    /// Mean reprojection error of all 3D points in pixels.
+    pub fn mean_reprojection_error(&self) -> f64 {
+        if self.points3d.is_empty() {
+            return 0.0;
+        }
+        let total: f64 = self.points3d.values().map(|p| p.error).sum();
+        total / self.points3d.len() as f64
+    }
+
+    /// Images sorted by name, which is frame order for extracted video frames.
+    pub fn images_by_name(&self) -> Vec<&Image> {
+        let mut images: Vec<&Image> = self.images.values().collect();
+        images.sort_by(|a, b| a.name.cmp(&b.name));
+        images
+    }
+
+    /// Checks that images reference known cameras and tracks reference known images.
+    pub fn validate(&self) -> Result<()> {
+        for image in self.images.values() {
+            if !self.cameras.contains_key(&image.camera_id) {
+                return Err(anyhow!(
+                    "Image {} references unknown camera {}",
+                    image.name,
+                    image.camera_id
+                ));
+            }
+        }
+        for point in self.points3d.values() {
+            for element in &point.track {
+                let image = self.images.get(&element.image_id).ok_or_else(|| {
+                    anyhow!(
+                        "Point {} references unknown image {}",
+                        point.id,
+                        element.image_id
+                    )
+                })?;
+                if element.point2d_idx as usize >= image.points2d.len() {
+                    return Err(anyhow!(
+                        "Point {} references keypoint {} of image {}, which has only {}",
+                        point.id,
+                        element.point2d_idx,
+                        image.name,
+                        image.points2d.len()
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
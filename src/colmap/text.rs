@@ -0,0 +1,455 @@
+//! Reader and writer for COLMAP's TXT model format.
+//!
+//! See <https://colmap.github.io/format.html#text-format> for the layout of
+//! `cameras.txt`, `images.txt` and `points3D.txt`.
+
+use super::{Camera, CameraModel, Image, Point2D, Point3D, Reconstruction, TrackElement};
+use anyhow::{anyhow, Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Returns whether a directory contains a TXT model.
+pub fn exists(dir: &Path) -> bool {
+    ["cameras.txt", "images.txt", "points3D.txt"]
+        .iter()
+        .all(|name| dir.join(name).exists())
+}
+
+/// Reads `cameras.txt`, `images.txt` and `points3D.txt` from a directory.
+pub fn read_model(dir: &Path) -> Result<Reconstruction> {
+    let cameras = read_cameras(&dir.join("cameras.txt"))?;
+    let images = read_images(&dir.join("images.txt"))?;
+    let points3d = read_points3d(&dir.join("points3D.txt"))?;
+    Ok(Reconstruction {
+        cameras,
+        images,
+        points3d,
+    })
+}
+
+/// Parses a single token.
+fn parse<T: FromStr>(token: &str, what: &str) -> Result<T> {
+    token
+        .parse()
+        .map_err(|_| anyhow!("invalid {}: {}", what, token))
+}
+
+/// Parses the next whitespace-separated token of a line.
+fn next<T: FromStr>(tokens: &mut std::str::SplitWhitespace, what: &str) -> Result<T> {
+    let token = tokens.next().ok_or_else(|| anyhow!("missing {}", what))?;
+    parse(token, what)
+}
+
+/// Reads a model file, skipping `#` comment lines, indented or not. Blank lines
+/// are kept, as empty lines, because an image without keypoints has an empty
+/// second line in `images.txt`. Other lines keep their trailing whitespace, which
+/// can be part of an image name.
+fn read_lines(path: &Path) -> Result<Vec<(usize, String)>> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim_start().starts_with('#'))
+        .map(|(i, line)| {
+            let line = line.trim_end_matches(['\r', '\n']);
+            let line = if line.trim().is_empty() { "" } else { line };
+            (i + 1, line.to_string())
+        })
+        .collect())
+}
+
+fn line_context(path: &Path, line_number: usize) -> String {
+    format!("{}:{}", path.display(), line_number)
+}
+
+/// Parses `cameras.txt`.
+pub fn read_cameras(path: &Path) -> Result<BTreeMap<u32, Camera>> {
+    let mut cameras = BTreeMap::new();
+    for (line_number, line) in read_lines(path)? {
+        if line.is_empty() {
+            continue;
+        }
+        let camera = parse_camera(&line).with_context(|| line_context(path, line_number))?;
+        cameras.insert(camera.id, camera);
+    }
+    Ok(cameras)
+}
+
+fn parse_camera(line: &str) -> Result<Camera> {
+    let mut tokens = line.split_whitespace();
+    let id = next(&mut tokens, "camera id")?;
+    let model: CameraModel = tokens
+        .next()
+        .ok_or_else(|| anyhow!("missing camera model"))?
+        .parse()?;
+    let width = next(&mut tokens, "width")?;
+    let height = next(&mut tokens, "height")?;
+    let params = tokens
+        .map(|t| parse(t, "parameter"))
+        .collect::<Result<Vec<f64>>>()?;
+    if params.len() != model.num_params() {
+        return Err(anyhow!(
+            "{} expects {} parameters, found {}",
+            model,
+            model.num_params(),
+            params.len()
+        ));
+    }
+    Ok(Camera {
+        id,
+        model,
+        width,
+        height,
+        params,
+    })
+}
+
+/// Parses `images.txt`, where every image takes two lines: the pose, then the
+/// keypoints.
+pub fn read_images(path: &Path) -> Result<BTreeMap<u32, Image>> {
+    let mut images = BTreeMap::new();
+    let mut lines = read_lines(path)?.into_iter();
+    while let Some((line_number, line)) = lines.next() {
+        if line.is_empty() {
+            continue;
+        }
+        let mut image = parse_image(&line).with_context(|| line_context(path, line_number))?;
+        if let Some((line_number, points_line)) = lines.next() {
+            image.points2d =
+                parse_points2d(&points_line).with_context(|| line_context(path, line_number))?;
+        }
+        images.insert(image.id, image);
+    }
+    Ok(images)
+}
+
+fn parse_image(line: &str) -> Result<Image> {
+    let mut tokens = line.split_whitespace();
+    let id = next(&mut tokens, "image id")?;
+    let mut qvec = [0.0; 4];
+    for q in &mut qvec {
+        *q = next(&mut tokens, "quaternion")?;
+    }
+    let mut tvec = [0.0; 3];
+    for t in &mut tvec {
+        *t = next(&mut tokens, "translation")?;
+    }
+    let camera_id = next(&mut tokens, "camera id")?;
+    // The name is the rest of the line, spaces included.
+    let name = after_fields(line, 9).to_string();
+    if name.is_empty() {
+        return Err(anyhow!("missing image name"));
+    }
+    Ok(Image {
+        id,
+        qvec,
+        tvec,
+        camera_id,
+        name,
+        points2d: Vec::new(),
+    })
+}
+
+/// Returns the rest of a line after its first `n` whitespace-separated fields and
+/// the single whitespace character that ends them.
+fn after_fields(line: &str, n: usize) -> &str {
+    let mut rest = line;
+    for _ in 0..n {
+        rest = rest.trim_start();
+        rest = &rest[rest.find(char::is_whitespace).unwrap_or(rest.len())..];
+    }
+    let mut chars = rest.chars();
+    chars.next();
+    chars.as_str()
+}
+
+fn parse_points2d(line: &str) -> Result<Vec<Point2D>> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    if !tokens.len().is_multiple_of(3) {
+        return Err(anyhow!(
+            "expected triplets of X Y POINT3D_ID, found {} values",
+            tokens.len()
+        ));
+    }
+    tokens
+        .chunks(3)
+        .map(|chunk| {
+            let point3d_id: i64 = parse(chunk[2], "point3D id")?;
+            Ok(Point2D {
+                xy: [
+                    parse(chunk[0], "keypoint x")?,
+                    parse(chunk[1], "keypoint y")?,
+                ],
+                // COLMAP writes -1 for keypoints without a 3D point.
+                point3d_id: u64::try_from(point3d_id).ok(),
+            })
+        })
+        .collect()
+}
+
+/// Parses `points3D.txt`.
+pub fn read_points3d(path: &Path) -> Result<BTreeMap<u64, Point3D>> {
+    let mut points = BTreeMap::new();
+    for (line_number, line) in read_lines(path)? {
+        if line.is_empty() {
+            continue;
+        }
+        let point = parse_point3d(&line).with_context(|| line_context(path, line_number))?;
+        points.insert(point.id, point);
+    }
+    Ok(points)
+}
+
+fn parse_point3d(line: &str) -> Result<Point3D> {
+    let mut tokens = line.split_whitespace();
+    let id = next(&mut tokens, "point3D id")?;
+    let mut xyz = [0.0; 3];
+    for v in &mut xyz {
+        *v = next(&mut tokens, "position")?;
+    }
+    let mut rgb = [0u8; 3];
+    for c in &mut rgb {
+        *c = next(&mut tokens, "color")?;
+    }
+    let error = next(&mut tokens, "error")?;
+    let rest: Vec<&str> = tokens.collect();
+    if !rest.len().is_multiple_of(2) {
+        return Err(anyhow!("track has an odd number of values"));
+    }
+    let track = rest
+        .chunks(2)
+        .map(|pair| {
+            Ok(TrackElement {
+                image_id: parse(pair[0], "track image id")?,
+                point2d_idx: parse(pair[1], "track keypoint index")?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Point3D {
+        id,
+        xyz,
+        rgb,
+        error,
+        track,
+    })
+}
+
+/// Writes `cameras.txt`, `images.txt` and `points3D.txt` into a directory.
+pub fn write_model(dir: &Path, model: &Reconstruction) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    write_file(&dir.join("cameras.txt"), &cameras_to_string(model))?;
+    write_file(&dir.join("images.txt"), &images_to_string(model))?;
+    write_file(&dir.join("points3D.txt"), &points3d_to_string(model))?;
+    Ok(())
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<()> {
+    fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn join<T: ToString>(values: &[T]) -> String {
+    values
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn cameras_to_string(model: &Reconstruction) -> String {
+    let mut out = String::new();
+    out.push_str("# Camera list with one line of data per camera:\n");
+    out.push_str("#   CAMERA_ID, MODEL, WIDTH, HEIGHT, PARAMS[]\n");
+    out.push_str(&format!("# Number of cameras: {}\n", model.cameras.len()));
+    for camera in model.cameras.values() {
+        out.push_str(&format!(
+            "{} {} {} {} {}\n",
+            camera.id,
+            camera.model,
+            camera.width,
+            camera.height,
+            join(&camera.params)
+        ));
+    }
+    out
+}
+
+fn images_to_string(model: &Reconstruction) -> String {
+    let num_observations: usize = model.images.values().map(|i| i.num_points3d()).sum();
+    let mean_observations = if model.images.is_empty() {
+        0.0
+    } else {
+        num_observations as f64 / model.images.len() as f64
+    };
+
+    let mut out = String::new();
+    out.push_str("# Image list with two lines of data per image:\n");
+    out.push_str("#   IMAGE_ID, QW, QX, QY, QZ, TX, TY, TZ, CAMERA_ID, NAME\n");
+    out.push_str("#   POINTS2D[] as (X, Y, POINT3D_ID)\n");
+    out.push_str(&format!(
+        "# Number of images: {}, mean observations per image: {}\n",
+        model.images.len(),
+        mean_observations
+    ));
+    for image in model.images.values() {
+        out.push_str(&format!(
+            "{} {} {} {} {}\n",
+            image.id,
+            join(&image.qvec),
+            join(&image.tvec),
+            image.camera_id,
+            image.name
+        ));
+        let points: Vec<String> = image
+            .points2d
+            .iter()
+            .map(|p| {
+                let id = p
+                    .point3d_id
+                    .map_or_else(|| "-1".to_string(), |id| id.to_string());
+                format!("{} {} {}", p.xy[0], p.xy[1], id)
+            })
+            .collect();
+        out.push_str(&points.join(" "));
+        out.push('\n');
+    }
+    out
+}
+
+fn points3d_to_string(model: &Reconstruction) -> String {
+    let mut out = String::new();
+    out.push_str("# 3D point list with one line of data per point:\n");
+    out.push_str("#   POINT3D_ID, X, Y, Z, R, G, B, ERROR, TRACK[] as (IMAGE_ID, POINT2D_IDX)\n");
+    out.push_str(&format!(
+        "# Number of points: {}, mean track length: {}\n",
+        model.points3d.len(),
+        model.mean_track_length()
+    ));
+    for point in model.points3d.values() {
+        let track: Vec<String> = point
+            .track
+            .iter()
+            .map(|e| format!("{} {}", e.image_id, e.point2d_idx))
+            .collect();
+        out.push_str(&format!(
+            "{} {} {} {} {}\n",
+            point.id,
+            join(&point.xyz),
+            join(&point.rgb),
+            point.error,
+            track.join(" ")
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAMERAS: &str = "\
+# Camera list with one line of data per camera:
+#   CAMERA_ID, MODEL, WIDTH, HEIGHT, PARAMS[]
+1 SIMPLE_RADIAL 1920 1080 1500 960 540 0.01
+2 PINHOLE 640 480 500 510 320 240
+";
+
+    const IMAGES: &str = "\
+# Image list with two lines of data per image:
+#   IMAGE_ID, QW, QX, QY, QZ, TX, TY, TZ, CAMERA_ID, NAME
+#   POINTS2D[] as (X, Y, POINT3D_ID)
+1 1 0 0 0 0.5 0 -2 1 frame_000001.jpg
+10.5 20.5 7 30 40 -1
+2 0.7071067811865476 0 0.7071067811865476 0 0 0 0 2 shot  01	b.jpg
+
+";
+
+    const POINTS3D: &str = "\
+# 3D point list with one line of data per point:
+#   POINT3D_ID, X, Y, Z, R, G, B, ERROR, TRACK[] as (IMAGE_ID, POINT2D_IDX)
+7 1 2 3 255 128 0 0.5 1 0
+";
+
+    #[test]
+    fn reads_a_small_model() {
+        let dir = std::env::temp_dir().join(format!("polyfjord3d-text-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("cameras.txt"), CAMERAS).unwrap();
+        fs::write(dir.join("images.txt"), IMAGES).unwrap();
+        fs::write(dir.join("points3D.txt"), POINTS3D).unwrap();
+
+        let model = read_model(&dir).unwrap();
+        model.validate().unwrap();
+
+        let camera = &model.cameras[&1];
+        assert_eq!(camera.model, CameraModel::SimpleRadial);
+        assert_eq!((camera.width, camera.height), (1920, 1080));
+        assert_eq!(camera.focal_length(), (1500.0, 1500.0));
+        assert_eq!(camera.principal_point(), (960.0, 540.0));
+        assert_eq!(camera.distortion(), [0.01]);
+        assert_eq!(model.cameras[&2].focal_length(), (500.0, 510.0));
+
+        let image = &model.images[&1];
+        assert_eq!(image.name, "frame_000001.jpg");
+        assert_eq!(image.camera_id, 1);
+        assert_eq!(image.camera_center(), [-0.5, 0.0, 2.0]);
+        assert_eq!(image.points2d.len(), 2);
+        assert_eq!(image.points2d[0].xy, [10.5, 20.5]);
+        assert_eq!(image.points2d[0].point3d_id, Some(7));
+        assert_eq!(image.points2d[1].point3d_id, None);
+        assert_eq!(image.num_points3d(), 1);
+        assert!(model.images[&2].points2d.is_empty());
+        assert_eq!(model.images[&2].name, "shot  01\tb.jpg");
+
+        let point = &model.points3d[&7];
+        assert_eq!(point.xyz, [1.0, 2.0, 3.0]);
+        assert_eq!(point.rgb, [255, 128, 0]);
+        assert_eq!(
+            point.track,
+            [TrackElement {
+                image_id: 1,
+                point2d_idx: 0
+            }]
+        );
+
+        // Writing and reading again gives the same model.
+        let copy_dir = dir.join("copy");
+        write_model(&copy_dir, &model).unwrap();
+        assert_eq!(read_model(&copy_dir).unwrap(), model);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_trailing_whitespace_of_image_names() {
+        let dir = std::env::temp_dir().join(format!("polyfjord3d-names-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("images.txt");
+        fs::write(
+            &path,
+            "  # indented comment\r\n3 1 0 0 0 0 0 0 1 take 2 .jpg \r\n \r\n",
+        )
+        .unwrap();
+
+        let images = read_images(&path).unwrap();
+        assert_eq!(images[&3].name, "take 2 .jpg ");
+        assert!(images[&3].points2d.is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reports_the_line_of_a_malformed_camera() {
+        let dir = std::env::temp_dir().join(format!("polyfjord3d-bad-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cameras.txt");
+        fs::write(&path, "# comment\n1 PINHOLE 640 480 500\n").unwrap();
+
+        let error = format!("{:#}", read_cameras(&path).unwrap_err());
+        assert!(error.contains("cameras.txt:2"), "{}", error);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
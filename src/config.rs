@@ -0,0 +1,430 @@
+//! Project configuration file (`polyfjord3d.toml`).
+//!
+//! The options passed to ffmpeg, COLMAP and GLOMAP can be overridden per step in a
+//! TOML file. The file is looked up next to each video unless `--config` is given,
+//! command-line flags take priority over it, and the merged result is saved into
+//! the scene directory so the run can be reproduced. Runs that start at a later
+//! step save theirs next to it without replacing it.
+//!
+//! ```toml
+//! [extract]
+//! fps = 4
+//!
+//! [feature_extractor]
+//! SiftExtraction.max_image_size = 2000
+//!
+//! [matcher]
+//! strategy = "vocab-tree"
+//! vocab_tree.vocab_tree_path = "vocab_tree_flickr100K_words256K.bin"
+//!
+//! [mapper.colmap]
+//! Mapper.ba_global_max_num_iterations = 30
+//! ```
+
+use crate::matcher::{MatcherConfig, SequentialOptions};
+use crate::Tool;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// File name of the project configuration file.
+pub const CONFIG_FILE_NAME: &str = "polyfjord3d.toml";
+
+/// Options of every pipeline step.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Frame extraction with ffmpeg.
+    pub extract: ExtractConfig,
+    /// Sharpness-based keyframe selection.
+    pub keyframes: KeyframeConfig,
+    /// Options passed to `colmap feature_extractor`.
+    pub feature_extractor: ToolOptions,
+    /// The feature matcher and its options.
+    pub matcher: MatcherConfig,
+    /// Options passed to the `mapper` of COLMAP and GLOMAP.
+    pub mapper: MapperConfig,
+    /// Options passed to `colmap image_undistorter`.
+    pub image_undistorter: ToolOptions,
+    /// The optional dense reconstruction steps.
+    pub dense: DenseConfig,
+    /// Options passed to `colmap patch_match_stereo`.
+    pub patch_match_stereo: ToolOptions,
+    /// Options passed to `colmap stereo_fusion`.
+    pub stereo_fusion: ToolOptions,
+    /// Options passed to `colmap poisson_mesher`.
+    pub poisson_mesher: ToolOptions,
+    /// Options passed to `colmap delaunay_mesher`.
+    pub delaunay_mesher: ToolOptions,
+}
+
+/// Options of the frame extraction step.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExtractConfig {
+    /// Extract frames at this rate instead of every frame.
+    pub fps: Option<f64>,
+    /// Extract only every Nth frame of the video.
+    pub every_nth_frame: Option<u32>,
+    /// Stop after extracting this many frames.
+    pub max_frames: Option<u32>,
+    /// JPEG quality passed to ffmpeg as `-qscale:v` (2 is best, 31 is worst).
+    pub qscale: Option<u32>,
+    /// How the frames of image folders and sequences are brought into the scene.
+    pub import: Option<ImportMode>,
+}
+
+/// How the frames of an image folder or sequence are brought into the scene.
+///
+/// Frames COLMAP cannot read, such as EXR or DPX, are always converted, and so are
+/// frames other than JPEG when keyframe selection is enabled.
+#[derive(clap::ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ImportMode {
+    /// Copy the images into the scene.
+    Copy,
+    /// Link to the original images instead of copying them.
+    Symlink,
+    /// Convert the images to JPEG with ffmpeg.
+    Convert,
+}
+
+impl ExtractConfig {
+    /// Number of video frames between two consecutive extracted frames, if known.
+    pub fn frame_step(&self) -> u32 {
+        match (self.fps, self.every_nth_frame) {
+            (None, Some(n)) => n.max(1),
+            _ => 1,
+        }
+    }
+
+    /// Builds the ffmpeg video filter graph for the selected sampling, if any.
+    pub fn filter_graph(&self) -> Option<String> {
+        if let Some(fps) = self.fps {
+            Some(format!("fps={}", fps))
+        } else {
+            self.every_nth_frame
+                .filter(|&n| n > 1)
+                .map(|n| format!("select=not(mod(n\\,{}))", n))
+        }
+    }
+}
+
+/// Options of the keyframe selection step.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyframeConfig {
+    /// Keep only the frames that are the sharpest of some `window` consecutive
+    /// frames (a sliding window).
+    pub window: Option<u32>,
+}
+
+/// Options of the mapping step.
+///
+/// COLMAP's and GLOMAP's mappers do not share option names, so each has its own
+/// table, `[mapper.colmap]` and `[mapper.glomap]`. Options directly under
+/// `[mapper]` go to the mapper of `--tool` only, never to the mapper of
+/// `--fallback-tool`.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct MapperConfig {
+    /// Options of `colmap mapper`.
+    #[serde(skip_serializing_if = "ToolOptions::is_empty")]
+    pub colmap: ToolOptions,
+    /// Options of `glomap mapper`.
+    #[serde(skip_serializing_if = "ToolOptions::is_empty")]
+    pub glomap: ToolOptions,
+    /// Options of the mapper of `--tool`.
+    #[serde(flatten)]
+    pub options: ToolOptions,
+}
+
+impl MapperConfig {
+    /// Returns the options of the mapper of `tool`.
+    ///
+    /// # Arguments
+    ///
+    /// * `tool` - The tool whose mapper runs.
+    /// * `fallback` - Whether the mapper runs for `--fallback-tool`, which does not
+    ///   get the options directly under `[mapper]`.
+    pub fn tool_options(&self, tool: Tool, fallback: bool) -> ToolOptions {
+        let mut options = if fallback {
+            ToolOptions::default()
+        } else {
+            self.options.clone()
+        };
+        options.merge(match tool {
+            Tool::Colmap => self.colmap.clone(),
+            Tool::Glomap => self.glomap.clone(),
+        });
+        options
+    }
+
+    /// Overrides the options of `self` with every option set in `other`.
+    pub fn merge(&mut self, other: MapperConfig) {
+        self.colmap.merge(other.colmap);
+        self.glomap.merge(other.glomap);
+        self.options.merge(other.options);
+    }
+}
+
+impl<'de> Deserialize<'de> for MapperConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut config = MapperConfig::default();
+        for (key, value) in ToolOptions::deserialize(deserializer)? {
+            if let Some(option) = key.strip_prefix("colmap.") {
+                config.colmap.set(option, value);
+            } else if let Some(option) = key.strip_prefix("glomap.") {
+                config.glomap.set(option, value);
+            } else {
+                config.options.set(&key, value);
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Options of the dense reconstruction steps.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct DenseConfig {
+    /// Run image undistortion, stereo matching and fusion after the mapper.
+    pub enabled: Option<bool>,
+    /// Turn the fused point cloud into a mesh with this mesher.
+    pub mesher: Option<Mesher>,
+}
+
+/// The COLMAP mesher used to mesh the dense point cloud.
+#[derive(clap::ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mesher {
+    /// Screened Poisson surface reconstruction. Smooth, watertight meshes.
+    Poisson,
+    /// Delaunay triangulation of the fused points. Keeps sharp edges.
+    Delaunay,
+}
+
+impl Mesher {
+    /// Name of the COLMAP command running this mesher.
+    pub fn command(self) -> &'static str {
+        match self {
+            Mesher::Poisson => "poisson_mesher",
+            Mesher::Delaunay => "delaunay_mesher",
+        }
+    }
+
+    /// File name of the mesh inside the dense workspace.
+    pub fn output_file(self) -> &'static str {
+        match self {
+            Mesher::Poisson => "meshed-poisson.ply",
+            Mesher::Delaunay => "meshed-delaunay.ply",
+        }
+    }
+}
+
+/// `--Section.option value` pairs passed to a COLMAP or GLOMAP command.
+///
+/// Keys may be written as TOML dotted keys (`SiftExtraction.use_gpu = 1`) or as
+/// quoted keys (`"SiftExtraction.use_gpu" = 1`); both mean the same option.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(transparent)]
+pub struct ToolOptions(BTreeMap<String, toml::Value>);
+
+impl ToolOptions {
+    fn from_pairs<const N: usize>(pairs: [(&str, toml::Value); N]) -> Self {
+        ToolOptions(
+            pairs
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    /// Returns whether an option is set.
+    pub fn contains(&self, key: &str) -> bool {
+        self.0.contains_key(key)
+    }
+
+    /// Returns whether no option is set.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Sets an option, replacing any previous value.
+    pub fn set(&mut self, key: &str, value: impl Into<toml::Value>) {
+        self.0.insert(key.to_string(), value.into());
+    }
+
+    /// Adds every option to a command as `--key value`.
+    pub fn apply(&self, command: &mut Command) {
+        for (key, value) in &self.0 {
+            command.arg(format!("--{}", key)).arg(option_value(value));
+        }
+    }
+
+    /// Overrides options with the ones from `other`.
+    pub fn merge(&mut self, other: ToolOptions) {
+        self.0.extend(other.0);
+    }
+}
+
+impl IntoIterator for ToolOptions {
+    type Item = (String, toml::Value);
+    type IntoIter = std::collections::btree_map::IntoIter<String, toml::Value>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'de> Deserialize<'de> for ToolOptions {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let table = toml::Table::deserialize(deserializer)?;
+        let mut options = BTreeMap::new();
+        flatten_table("", table, &mut options);
+        Ok(ToolOptions(options))
+    }
+}
+
+/// Turns nested tables produced by dotted keys back into `Section.option` keys.
+fn flatten_table(prefix: &str, table: toml::Table, out: &mut BTreeMap<String, toml::Value>) {
+    for (key, value) in table {
+        let key = if prefix.is_empty() {
+            key
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        match value {
+            toml::Value::Table(inner) => flatten_table(&key, inner, out),
+            value => {
+                out.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Formats a TOML value the way COLMAP expects it on the command line.
+fn option_value(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(s) => s.clone(),
+        toml::Value::Boolean(b) => (if *b { "1" } else { "0" }).to_string(),
+        other => other.to_string(),
+    }
+}
+
+impl Config {
+    /// The options polyfjord3d uses when nothing else is configured.
+    pub fn builtin() -> Self {
+        Config {
+            extract: ExtractConfig {
+                qscale: Some(2),
+                ..Default::default()
+            },
+            keyframes: KeyframeConfig::default(),
+            feature_extractor: ToolOptions::from_pairs([
+                ("ImageReader.single_camera", 1.into()),
+                ("SiftExtraction.use_gpu", 1.into()),
+                ("SiftExtraction.max_image_size", 4096.into()),
+            ]),
+            matcher: MatcherConfig {
+                sequential: SequentialOptions {
+                    overlap: Some(15),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            mapper: MapperConfig::default(),
+            image_undistorter: ToolOptions::default(),
+            dense: DenseConfig::default(),
+            patch_match_stereo: ToolOptions::from_pairs([(
+                "PatchMatchStereo.geom_consistency",
+                true.into(),
+            )]),
+            stereo_fusion: ToolOptions::from_pairs([("input_type", "geometric".into())]),
+            poisson_mesher: ToolOptions::default(),
+            delaunay_mesher: ToolOptions::default(),
+        }
+    }
+
+    /// Parses a configuration file.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))
+    }
+
+    /// Overrides the options of `self` with every option set in `other`.
+    pub fn merge(&mut self, other: Config) {
+        let extract = other.extract;
+        if extract.fps.is_some() || extract.every_nth_frame.is_some() {
+            // The two sampling modes exclude each other, so one replaces the other.
+            self.extract.fps = extract.fps;
+            self.extract.every_nth_frame = extract.every_nth_frame;
+        }
+        self.extract.max_frames = extract.max_frames.or(self.extract.max_frames);
+        self.extract.qscale = extract.qscale.or(self.extract.qscale);
+        self.extract.import = extract.import.or(self.extract.import);
+        self.keyframes.window = other.keyframes.window.or(self.keyframes.window);
+        self.feature_extractor.merge(other.feature_extractor);
+        self.matcher.merge(other.matcher);
+        self.mapper.merge(other.mapper);
+        self.image_undistorter.merge(other.image_undistorter);
+        self.dense.enabled = other.dense.enabled.or(self.dense.enabled);
+        self.dense.mesher = other.dense.mesher.or(self.dense.mesher);
+        self.patch_match_stereo.merge(other.patch_match_stereo);
+        self.stereo_fusion.merge(other.stereo_fusion);
+        self.poisson_mesher.merge(other.poisson_mesher);
+        self.delaunay_mesher.merge(other.delaunay_mesher);
+    }
+
+    /// Writes the configuration as TOML.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, toml::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write config file {}", path.display()))
+    }
+}
+
+/// Finds the configuration file for a video: the explicit `--config` path, or a
+/// `polyfjord3d.toml` next to the video.
+pub fn find_config_file(explicit: Option<&Path>, video_path: &Path) -> Option<PathBuf> {
+    if let Some(path) = explicit {
+        return Some(path.to_path_buf());
+    }
+    let dir = video_path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let candidate = dir.join(CONFIG_FILE_NAME);
+    candidate.exists().then_some(candidate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fallback_mapper_only_gets_its_own_options() {
+        let config: Config = toml::from_str(
+            "[mapper]\n\
+             Mapper.ba_global_max_num_iterations = 30\n\
+             [mapper.glomap]\n\
+             GlobalPositioning.max_num_iterations = 100\n",
+        )
+        .unwrap();
+        let mapper = &config.mapper;
+        let colmap = mapper.tool_options(Tool::Colmap, false);
+        assert!(colmap.contains("Mapper.ba_global_max_num_iterations"));
+        assert!(!colmap.contains("GlobalPositioning.max_num_iterations"));
+        let glomap = mapper.tool_options(Tool::Glomap, true);
+        assert!(glomap.contains("GlobalPositioning.max_num_iterations"));
+        assert!(!glomap.contains("Mapper.ba_global_max_num_iterations"));
+
+        let saved: Config = toml::from_str(&toml::to_string_pretty(&config).unwrap()).unwrap();
+        assert_eq!(saved.mapper.options, mapper.options);
+        assert_eq!(saved.mapper.glomap, mapper.glomap);
+        assert!(saved.mapper.colmap.is_empty());
+    }
+}
@@ -0,0 +1,121 @@
+//! Export of the camera track and sparse points for Blender.
+//!
+//! Writes `camera_track.json`, a camera animation with one keyframe per registered
+//! frame, and `camera_track.py`, a standalone script that recreates the animated
+//! camera and the sparse points in Blender when run from its Text Editor.
+
+use super::{frame_number, ExportContext};
+use crate::colmap::{Camera, Image, Reconstruction};
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+
+/// Template of the import script. `__CAMERA_TRACK__` is replaced with the JSON data.
+const SCRIPT_TEMPLATE: &str = include_str!("blender_import.py");
+
+/// Sensor width used to convert focal lengths from pixels to millimeters.
+const SENSOR_WIDTH_MM: f64 = 36.0;
+
+#[derive(Serialize)]
+struct CameraTrack {
+    scene: String,
+    resolution: [u64; 2],
+    lens: f64,
+    sensor_width: f64,
+    shift: [f64; 2],
+    frames: Vec<Keyframe>,
+    points: Vec<[f64; 3]>,
+    colors: Vec<[u8; 3]>,
+}
+
+#[derive(Serialize)]
+struct Keyframe {
+    frame: u32,
+    image: String,
+    /// Camera-to-world matrix in Blender's camera convention (looking down -Z, Y up).
+    matrix_world: [[f64; 4]; 4],
+}
+
+/// Builds the camera-to-world matrix of an image in Blender's camera convention.
+///
+/// COLMAP cameras look down +Z with Y pointing down, Blender cameras look down -Z
+/// with Y pointing up, so the Y and Z axes are flipped.
+fn matrix_world(image: &Image) -> [[f64; 4]; 4] {
+    let r = image.rotation_matrix();
+    let c = image.camera_center();
+    let flip = [1.0, -1.0, -1.0];
+    let mut m = [[0.0; 4]; 4];
+    for row in 0..3 {
+        for col in 0..3 {
+            // Camera-to-world rotation is the transpose of COLMAP's rotation.
+            m[row][col] = r[col][row] * flip[col];
+        }
+        m[row][3] = c[row];
+    }
+    m[3][3] = 1.0;
+    m
+}
+
+/// Returns the camera used by most images.
+fn main_camera(model: &Reconstruction) -> Result<&Camera> {
+    let mut usage: BTreeMap<u32, usize> = BTreeMap::new();
+    for image in model.images.values() {
+        *usage.entry(image.camera_id).or_default() += 1;
+    }
+    let camera_id = usage
+        .into_iter()
+        .max_by_key(|&(_, count)| count)
+        .map(|(id, _)| id)
+        .ok_or_else(|| anyhow!("The model has no registered images"))?;
+    model
+        .cameras
+        .get(&camera_id)
+        .ok_or_else(|| anyhow!("Unknown camera {}", camera_id))
+}
+
+pub fn export(model: &Reconstruction, ctx: &ExportContext) -> Result<()> {
+    let camera = main_camera(model)?;
+    let (fx, _) = camera.focal_length();
+    let (cx, cy) = camera.principal_point();
+    let width = camera.width as f64;
+    let height = camera.height as f64;
+    // Blender's shift is relative to the larger image dimension.
+    let size = width.max(height);
+
+    let frames = model
+        .images_by_name()
+        .into_iter()
+        .enumerate()
+        .map(|(i, image)| Keyframe {
+            frame: frame_number(&image.name, ctx.frame_step).unwrap_or(i as u32 + 1),
+            image: image.name.clone(),
+            matrix_world: matrix_world(image),
+        })
+        .collect();
+
+    let track = CameraTrack {
+        scene: ctx.scene_name.to_string(),
+        resolution: [camera.width, camera.height],
+        lens: fx * SENSOR_WIDTH_MM / width,
+        sensor_width: SENSOR_WIDTH_MM,
+        shift: [-(cx - width / 2.0) / size, (cy - height / 2.0) / size],
+        frames,
+        points: model.points3d.values().map(|p| p.xyz).collect(),
+        colors: model.points3d.values().map(|p| p.rgb).collect(),
+    };
+
+    let json = serde_json::to_string(&track)?;
+    fs::write(ctx.scene_dir.join("camera_track.json"), &json)?;
+
+    // A JSON string literal is also a valid Python string literal.
+    let script = SCRIPT_TEMPLATE.replace("__CAMERA_TRACK__", &serde_json::to_string(&json)?);
+    fs::write(ctx.scene_dir.join("camera_track.py"), script)?;
+
+    ctx.job.println(format!(
+        "[INFO] Exported Blender camera track with {} keyframes to {}",
+        track.frames.len(),
+        ctx.scene_dir.join("camera_track.py").display()
+    ));
+    Ok(())
+}
@@ -0,0 +1,57 @@
+# Generated by polyfjord3d.
+# Open this file in Blender's Text Editor and press "Run Script" to create the
+# tracked camera and the sparse point cloud of the scene.
+
+import json
+
+import bpy
+from mathutils import Matrix
+
+DATA = json.loads(__CAMERA_TRACK__)
+
+scene = bpy.context.scene
+name = DATA["scene"]
+
+# Camera with one keyframe per registered frame.
+camera_data = bpy.data.cameras.new(name + "_camera")
+camera_data.sensor_fit = "HORIZONTAL"
+camera_data.sensor_width = DATA["sensor_width"]
+camera_data.lens = DATA["lens"]
+camera_data.shift_x = DATA["shift"][0]
+camera_data.shift_y = DATA["shift"][1]
+
+camera = bpy.data.objects.new(name + "_camera", camera_data)
+scene.collection.objects.link(camera)
+camera.rotation_mode = "XYZ"
+
+previous_rotation = None
+for keyframe in DATA["frames"]:
+    matrix = Matrix(keyframe["matrix_world"])
+    camera.location = matrix.to_translation()
+    if previous_rotation is None:
+        camera.rotation_euler = matrix.to_euler("XYZ")
+    else:
+        # Keep the rotation curves continuous instead of jumping by 360 degrees.
+        camera.rotation_euler = matrix.to_euler("XYZ", previous_rotation)
+    previous_rotation = camera.rotation_euler.copy()
+    camera.keyframe_insert("location", frame=keyframe["frame"])
+    camera.keyframe_insert("rotation_euler", frame=keyframe["frame"])
+
+if DATA["frames"]:
+    scene.frame_start = DATA["frames"][0]["frame"]
+    scene.frame_end = DATA["frames"][-1]["frame"]
+scene.render.resolution_x = DATA["resolution"][0]
+scene.render.resolution_y = DATA["resolution"][1]
+scene.camera = camera
+
+# Sparse points as a mesh made of vertices only.
+mesh = bpy.data.meshes.new(name + "_points")
+mesh.from_pydata(DATA["points"], [], [])
+if DATA["colors"] and hasattr(mesh, "color_attributes"):
+    colors = mesh.color_attributes.new("Col", "BYTE_COLOR", "POINT")
+    for i, (r, g, b) in enumerate(DATA["colors"]):
+        colors.data[i].color = (r / 255.0, g / 255.0, b / 255.0, 1.0)
+mesh.update()
+
+points = bpy.data.objects.new(name + "_points", mesh)
+scene.collection.objects.link(points)
@@ -0,0 +1,71 @@
+//! Exporters that turn a reconstructed model into files for other applications.
+//!
+//! Exports run at the end of the export step, after the TXT model has been written
+//! to `sparse/`, and place their output in the scene directory.
+
+mod blender;
+mod ply;
+mod transforms;
+
+use crate::colmap::Reconstruction;
+use crate::scheduler::Job;
+use anyhow::Result;
+use std::path::Path;
+
+/// The additional formats a scene can be exported to.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Camera animation and sparse points as a Blender Python script and JSON file.
+    Blender,
+    /// Sparse points with colors as a binary PLY file.
+    Ply,
+    /// Sparse points with colors as an ASCII PLY file.
+    PlyAscii,
+    /// Camera poses and intrinsics as a nerfstudio `transforms.json`.
+    Nerfstudio,
+    /// Camera poses and intrinsics as an Instant-NGP `transforms_ngp.json`.
+    InstantNgp,
+}
+
+/// What the exporters need to know about a scene.
+pub struct ExportContext<'a> {
+    /// Name of the scene, used to name exported objects.
+    pub scene_name: &'a str,
+    /// Directory the exported files are written to.
+    pub scene_dir: &'a Path,
+    /// Directory holding the extracted frames the model was built from.
+    pub images_dir: &'a Path,
+    /// Number of video frames between two consecutive extracted frames. 1 with
+    /// `--fps`, so frame numbers count extracted frames then.
+    pub frame_step: u32,
+    /// The job of the scene, used to print messages.
+    pub job: &'a Job<'a>,
+}
+
+/// Exports a model to the given format.
+pub fn export(format: ExportFormat, model: &Reconstruction, ctx: &ExportContext) -> Result<()> {
+    match format {
+        ExportFormat::Blender => blender::export(model, ctx),
+        ExportFormat::Ply => ply::export(model, ctx, false),
+        ExportFormat::PlyAscii => ply::export(model, ctx, true),
+        ExportFormat::Nerfstudio => {
+            transforms::export(model, ctx, transforms::Convention::Nerfstudio)
+        }
+        ExportFormat::InstantNgp => {
+            transforms::export(model, ctx, transforms::Convention::InstantNgp)
+        }
+    }
+}
+
+/// Returns the video frame number of an extracted frame such as `frame_000042.jpg`.
+///
+/// ffmpeg numbers extracted frames from 1, so with a `frame_step` of 3 the
+/// second extracted frame is video frame 4.
+pub fn frame_number(image_name: &str, frame_step: u32) -> Option<u32> {
+    let stem = Path::new(image_name).file_stem()?.to_str()?;
+    let start = stem
+        .rfind(|c: char| !c.is_ascii_digit())
+        .map_or(0, |i| i + 1);
+    let index: u32 = stem[start..].parse().ok()?;
+    Some((index.max(1) - 1) * frame_step.max(1) + 1)
+}
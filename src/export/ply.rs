@@ -0,0 +1,56 @@
+//! Export of the sparse point cloud as a PLY file with RGB colors.
+
+use super::ExportContext;
+use crate::colmap::Reconstruction;
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// Writes `points3D.ply` into the scene directory, next to `sparse/`.
+pub fn export(model: &Reconstruction, ctx: &ExportContext, ascii: bool) -> Result<()> {
+    let path = ctx.scene_dir.join("points3D.ply");
+    let file =
+        File::create(&path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut w = BufWriter::new(file);
+
+    writeln!(w, "ply")?;
+    if ascii {
+        writeln!(w, "format ascii 1.0")?;
+    } else {
+        writeln!(w, "format binary_little_endian 1.0")?;
+    }
+    writeln!(
+        w,
+        "comment Generated by polyfjord3d from scene {}",
+        ctx.scene_name
+    )?;
+    writeln!(w, "element vertex {}", model.points3d.len())?;
+    for axis in ["x", "y", "z"] {
+        writeln!(w, "property double {}", axis)?;
+    }
+    for channel in ["red", "green", "blue"] {
+        writeln!(w, "property uchar {}", channel)?;
+    }
+    writeln!(w, "end_header")?;
+
+    for point in model.points3d.values() {
+        let [x, y, z] = point.xyz;
+        let [r, g, b] = point.rgb;
+        if ascii {
+            writeln!(w, "{} {} {} {} {} {}", x, y, z, r, g, b)?;
+        } else {
+            for v in point.xyz {
+                w.write_all(&v.to_le_bytes())?;
+            }
+            w.write_all(&point.rgb)?;
+        }
+    }
+    w.flush()?;
+
+    ctx.job.println(format!(
+        "[INFO] Exported {} points to {}",
+        model.points3d.len(),
+        path.display()
+    ));
+    Ok(())
+}
@@ -0,0 +1,430 @@
+//! Export of `transforms.json` for nerfstudio and Instant-NGP.
+//!
+//! Both formats store OpenGL-style camera-to-world matrices (camera looking down
+//! -Z with Y up) next to the intrinsics and distortion of each camera, and refer to
+//! the frames in `images/` relative to the JSON file.
+
+use super::ExportContext;
+use crate::colmap::{Camera, CameraModel, Image, Reconstruction};
+use anyhow::{bail, Result};
+use serde_json::{json, Map, Value};
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+type Matrix4 = [[f64; 4]; 4];
+
+/// The flavor of `transforms.json` to write.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Convention {
+    Nerfstudio,
+    InstantNgp,
+}
+
+impl Convention {
+    fn file_name(self) -> &'static str {
+        match self {
+            Convention::Nerfstudio => "transforms.json",
+            Convention::InstantNgp => "transforms_ngp.json",
+        }
+    }
+}
+
+fn identity() -> Matrix4 {
+    let mut m = [[0.0; 4]; 4];
+    for (i, row) in m.iter_mut().enumerate() {
+        row[i] = 1.0;
+    }
+    m
+}
+
+fn multiply(a: &Matrix4, b: &Matrix4) -> Matrix4 {
+    let mut m = [[0.0; 4]; 4];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
+
+/// The camera-to-world matrix of an image in COLMAP's (OpenCV) camera convention.
+fn camera_to_world(image: &Image) -> Matrix4 {
+    let r = image.rotation_matrix();
+    let c = image.camera_center();
+    let mut m = identity();
+    for row in 0..3 {
+        for col in 0..3 {
+            m[row][col] = r[col][row];
+        }
+        m[row][3] = c[row];
+    }
+    m
+}
+
+/// Converts an OpenCV camera-to-world matrix to OpenGL by flipping the camera's
+/// Y and Z axes.
+fn opencv_to_opengl(mut m: Matrix4) -> Matrix4 {
+    for row in m.iter_mut().take(3) {
+        row[1] = -row[1];
+        row[2] = -row[2];
+    }
+    m
+}
+
+/// Swaps the world's Y and Z axes and negates the new Z, like nerfstudio's COLMAP
+/// importer does.
+///
+/// Instant-NGP's `colmap2nerf.py` does not apply this transform: it swaps the
+/// first two rows of each pose, negates the third and then rotates the average up
+/// vector to +Z. The Instant-NGP export is only +Z up because
+/// [`normalize_for_instant_ngp`] does that rotation too, whatever transform came
+/// before it.
+const WORLD_TRANSFORM: Matrix4 = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, -1.0, 0.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+/// Rotation matrix that rotates the unit vector `a` onto the unit vector `b`.
+fn rotation_between(a: [f64; 3], b: [f64; 3]) -> Matrix4 {
+    let v = [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ];
+    let c = a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+    let s2 = v[0] * v[0] + v[1] * v[1] + v[2] * v[2];
+    let mut m = identity();
+    if s2 < 1e-20 {
+        if c < 0.0 {
+            // Opposite vectors: rotate by 180 degrees around the X axis.
+            m[1][1] = -1.0;
+            m[2][2] = -1.0;
+        }
+        return m;
+    }
+    let k = [[0.0, -v[2], v[1]], [v[2], 0.0, -v[0]], [-v[1], v[0], 0.0]];
+    let factor = (1.0 - c) / s2;
+    for i in 0..3 {
+        for j in 0..3 {
+            let k2: f64 = (0..3).map(|n| k[i][n] * k[n][j]).sum();
+            m[i][j] += k[i][j] + k2 * factor;
+        }
+    }
+    m
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn column(m: &Matrix4, col: usize) -> [f64; 3] {
+    [m[0][col], m[1][col], m[2][col]]
+}
+
+/// Returns the point closest to both rays `oa + t * da` and `ob + t * db` and a
+/// weight that goes to 0 as the rays become parallel, like `closest_point_2_lines`
+/// in `colmap2nerf.py`.
+fn closest_point_2_lines(
+    oa: [f64; 3],
+    da: [f64; 3],
+    ob: [f64; 3],
+    db: [f64; 3],
+) -> ([f64; 3], f64) {
+    let da_length = dot(da, da).sqrt();
+    let db_length = dot(db, db).sqrt();
+    let da = da.map(|x| x / da_length);
+    let db = db.map(|x| x / db_length);
+    let c = cross(da, db);
+    let denom = dot(c, c);
+    let t = [ob[0] - oa[0], ob[1] - oa[1], ob[2] - oa[2]];
+    // det([t, d, c]) is the triple product t . (d x c).
+    let ta = (dot(t, cross(db, c)) / (denom + 1e-10)).min(0.0);
+    let tb = (dot(t, cross(da, c)) / (denom + 1e-10)).min(0.0);
+    let mut point = [0.0; 3];
+    for (i, p) in point.iter_mut().enumerate() {
+        *p = (oa[i] + ta * da[i] + ob[i] + tb * db[i]) * 0.5;
+    }
+    (point, denom)
+}
+
+/// Normalizes the poses the way Instant-NGP's `colmap2nerf.py` does: the average
+/// camera up vector becomes +Z, the point the cameras look at (the weighted
+/// closest approach of every pair of optical axes) becomes the origin, and the
+/// cameras are scaled to an average distance of 4 units from it.
+fn normalize_for_instant_ngp(matrices: &mut [Matrix4]) {
+    if matrices.is_empty() {
+        return;
+    }
+
+    let mut up = [0.0; 3];
+    for m in matrices.iter() {
+        for (i, u) in up.iter_mut().enumerate() {
+            *u += m[i][1];
+        }
+    }
+    let length = dot(up, up).sqrt();
+    if length > 0.0 {
+        let up = up.map(|u| u / length);
+        let rotation = rotation_between(up, [0.0, 0.0, 1.0]);
+        for m in matrices.iter_mut() {
+            *m = multiply(&rotation, m);
+        }
+    }
+
+    // The cameras look down -Z, so the rays run backwards along the Z column and
+    // only the part of them behind the camera centers (t <= 0) counts.
+    let mut total_weight = 0.0;
+    let mut focus = [0.0; 3];
+    for mf in matrices.iter() {
+        for mg in matrices.iter() {
+            let (point, weight) =
+                closest_point_2_lines(column(mf, 3), column(mf, 2), column(mg, 3), column(mg, 2));
+            if weight > 0.00001 {
+                for (i, f) in focus.iter_mut().enumerate() {
+                    *f += point[i] * weight;
+                }
+                total_weight += weight;
+            }
+        }
+    }
+    if total_weight > 0.0 {
+        focus = focus.map(|f| f / total_weight);
+    }
+    for m in matrices.iter_mut() {
+        for (i, f) in focus.iter().enumerate() {
+            m[i][3] -= f;
+        }
+    }
+
+    let count = matrices.len() as f64;
+    let average_distance = matrices
+        .iter()
+        .map(|m| dot(column(m, 3), column(m, 3)).sqrt())
+        .sum::<f64>()
+        / count;
+    if average_distance > 0.0 {
+        let scale = 4.0 / average_distance;
+        for m in matrices.iter_mut() {
+            for row in m.iter_mut().take(3) {
+                row[3] *= scale;
+            }
+        }
+    }
+}
+
+/// Writes the intrinsics and distortion of a camera in the keys both formats use.
+fn intrinsics(
+    camera: &Camera,
+    convention: Convention,
+    ctx: &ExportContext,
+) -> Result<Map<String, Value>> {
+    let (fl_x, fl_y) = camera.focal_length();
+    let (cx, cy) = camera.principal_point();
+    let d = camera.distortion();
+
+    let mut map = Map::new();
+    map.insert("w".into(), json!(camera.width));
+    map.insert("h".into(), json!(camera.height));
+    map.insert("fl_x".into(), json!(fl_x));
+    map.insert("fl_y".into(), json!(fl_y));
+    map.insert("cx".into(), json!(cx));
+    map.insert("cy".into(), json!(cy));
+
+    let (fisheye, coefficients): (bool, Vec<(&str, f64)>) = match camera.model {
+        CameraModel::SimplePinhole | CameraModel::Pinhole => (false, vec![]),
+        CameraModel::SimpleRadial => (false, vec![("k1", d[0])]),
+        CameraModel::Radial => (false, vec![("k1", d[0]), ("k2", d[1])]),
+        CameraModel::OpenCV => (
+            false,
+            vec![("k1", d[0]), ("k2", d[1]), ("p1", d[2]), ("p2", d[3])],
+        ),
+        CameraModel::FullOpenCV => {
+            if d[5..].iter().any(|&k| k != 0.0) {
+                ctx.job.warn(&format!(
+                    "Camera {}: FULL_OPENCV coefficients k4-k6 are not supported by transforms.json and were dropped.",
+                    camera.id
+                ));
+            }
+            (
+                false,
+                vec![
+                    ("k1", d[0]),
+                    ("k2", d[1]),
+                    ("p1", d[2]),
+                    ("p2", d[3]),
+                    ("k3", d[4]),
+                ],
+            )
+        }
+        CameraModel::OpenCVFisheye => (
+            true,
+            vec![("k1", d[0]), ("k2", d[1]), ("k3", d[2]), ("k4", d[3])],
+        ),
+        CameraModel::SimpleRadialFisheye => (true, vec![("k1", d[0])]),
+        CameraModel::RadialFisheye => (true, vec![("k1", d[0]), ("k2", d[1])]),
+        model => bail!(
+            "Camera {} uses the {} model, which transforms.json cannot describe",
+            camera.id,
+            model
+        ),
+    };
+    for (key, value) in coefficients {
+        map.insert(key.into(), json!(value));
+    }
+
+    match convention {
+        Convention::Nerfstudio => {
+            let model = if fisheye { "OPENCV_FISHEYE" } else { "OPENCV" };
+            map.insert("camera_model".into(), json!(model));
+        }
+        Convention::InstantNgp => {
+            let width = camera.width as f64;
+            let height = camera.height as f64;
+            map.insert(
+                "camera_angle_x".into(),
+                json!(2.0 * (width / (2.0 * fl_x)).atan()),
+            );
+            map.insert(
+                "camera_angle_y".into(),
+                json!(2.0 * (height / (2.0 * fl_y)).atan()),
+            );
+            map.insert("is_fisheye".into(), json!(fisheye));
+        }
+    }
+    Ok(map)
+}
+
+/// Joins an image name to the images directory with forward slashes, which both
+/// nerfstudio and Instant-NGP accept on every platform.
+fn file_path(images_dir: &Path, image_name: &str) -> String {
+    let dir = images_dir.to_string_lossy().replace('\\', "/");
+    if dir.is_empty() {
+        image_name.to_string()
+    } else {
+        format!("{}/{}", dir.trim_end_matches('/'), image_name)
+    }
+}
+
+/// Writes `transforms.json` (nerfstudio) or `transforms_ngp.json` (Instant-NGP)
+/// into the scene directory.
+pub fn export(model: &Reconstruction, ctx: &ExportContext, convention: Convention) -> Result<()> {
+    let images = model.images_by_name();
+    let mut matrices: Vec<Matrix4> = images
+        .iter()
+        .map(|image| {
+            let c2w = opencv_to_opengl(camera_to_world(image));
+            multiply(&WORLD_TRANSFORM, &c2w)
+        })
+        .collect();
+    if convention == Convention::InstantNgp {
+        normalize_for_instant_ngp(&mut matrices);
+    }
+
+    // Paths are relative to the JSON file when the images live below the scene.
+    let images_dir = ctx
+        .images_dir
+        .strip_prefix(ctx.scene_dir)
+        .unwrap_or(ctx.images_dir);
+
+    // Each camera is described (and warned about) once, however many frames use it.
+    let mut camera_intrinsics = BTreeMap::new();
+    for image in &images {
+        if let Entry::Vacant(entry) = camera_intrinsics.entry(image.camera_id) {
+            entry.insert(intrinsics(
+                &model.cameras[&image.camera_id],
+                convention,
+                ctx,
+            )?);
+        }
+    }
+
+    let single_camera = model.cameras.len() == 1;
+    let mut root = Map::new();
+    if single_camera {
+        if let Some(map) = camera_intrinsics.values().next() {
+            root.extend(map.clone());
+        }
+    }
+
+    let mut frames = Vec::with_capacity(images.len());
+    for (image, matrix) in images.iter().zip(&matrices) {
+        let mut frame = Map::new();
+        frame.insert(
+            "file_path".into(),
+            json!(file_path(images_dir, &image.name)),
+        );
+        frame.insert("transform_matrix".into(), json!(matrix));
+        frame.insert("colmap_im_id".into(), json!(image.id));
+        if !single_camera {
+            frame.extend(camera_intrinsics[&image.camera_id].clone());
+        }
+        frames.push(Value::Object(frame));
+    }
+
+    match convention {
+        Convention::Nerfstudio => {
+            root.insert("applied_transform".into(), json!(&WORLD_TRANSFORM[..3]));
+        }
+        Convention::InstantNgp => {
+            root.insert("aabb_scale".into(), json!(16));
+        }
+    }
+    root.insert("frames".into(), Value::Array(frames));
+
+    let path = ctx.scene_dir.join(convention.file_name());
+    fs::write(&path, serde_json::to_string_pretty(&Value::Object(root))?)?;
+    ctx.job.println(format!(
+        "[INFO] Exported {} camera poses to {}",
+        images.len(),
+        path.display()
+    ));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instant_ngp_poses_are_centered_on_the_point_the_cameras_look_at() {
+        // Cameras on half a circle around (5, 1, 3), looking at its center with +Z up.
+        let center = [5.0, 1.0, 3.0];
+        let mut matrices: Vec<Matrix4> = (0..6)
+            .map(|i| {
+                let angle = std::f64::consts::PI * i as f64 / 5.0;
+                let back = [angle.cos(), angle.sin(), 0.0];
+                let up = [0.0, 0.0, 1.0];
+                let right = cross(up, back);
+                let mut m = identity();
+                for row in 0..3 {
+                    m[row][0] = right[row];
+                    m[row][1] = up[row];
+                    m[row][2] = back[row];
+                    m[row][3] = center[row] + 2.0 * back[row];
+                }
+                m
+            })
+            .collect();
+
+        normalize_for_instant_ngp(&mut matrices);
+
+        for m in &matrices {
+            let distance = dot(column(m, 3), column(m, 3)).sqrt();
+            assert!((distance - 4.0).abs() < 1e-6, "distance {}", distance);
+            assert!(m[2][3].abs() < 1e-6);
+            assert!((m[2][1] - 1.0).abs() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,609 @@
+//! The inputs a scene can be built from.
+//!
+//! Besides videos, a positional input may be a folder of stills or an image
+//! sequence given as a pattern such as `shots/shot_####.png`. In a pattern `#`
+//! stands for a digit, `?` for any character and `*` for any run of characters.
+//! The frames of such inputs are imported into the images directory instead of
+//! being extracted with ffmpeg.
+//!
+//! Every input becomes its own scene, unless `--merge-into` combines all of them
+//! into one scene with a subfolder of images per input. Scene names are checked
+//! for collisions before anything is processed, since two inputs with the same
+//! name would otherwise share a scene directory.
+
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// Extensions of the image files picked up from folders, in lower case.
+const IMAGE_EXTENSIONS: [&str; 11] = [
+    "jpg", "jpeg", "png", "tif", "tiff", "bmp", "exr", "dpx", "webp", "hdr", "tga",
+];
+
+/// Extensions of the image formats COLMAP reads directly, in lower case.
+const COLMAP_EXTENSIONS: [&str; 6] = ["jpg", "jpeg", "png", "tif", "tiff", "bmp"];
+
+/// A scene to reconstruct and the inputs its frames come from.
+#[derive(Debug, Clone)]
+pub struct SceneInput {
+    /// Name of the scene directory.
+    pub name: String,
+    /// The videos, image folders and image sequences of the scene.
+    pub sources: Vec<PathBuf>,
+    /// Whether the frames of each source go to their own subfolder of the images
+    /// directory, with a camera per subfolder.
+    pub merged: bool,
+}
+
+impl SceneInput {
+    /// Returns the subfolder of the images directory for each source: the name the
+    /// source would give a scene of its own, numbered if several sources share it.
+    pub fn folder_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::with_capacity(self.sources.len());
+        for source in &self.sources {
+            let base = scene_name(source);
+            let mut name = base.clone();
+            let mut n = 2;
+            while names.contains(&name) {
+                name = format!("{}_{}", base, n);
+                n += 1;
+            }
+            names.push(name);
+        }
+        names
+    }
+
+    /// Returns the inputs of the scene for messages.
+    pub fn describe(&self) -> String {
+        let sources: Vec<String> = self
+            .sources
+            .iter()
+            .map(|source| source.display().to_string())
+            .collect();
+        sources.join(", ")
+    }
+}
+
+/// How the name of a scene is derived from its input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SceneNaming {
+    /// The name of the input without extension, e.g. `take1`.
+    Stem,
+    /// The name of the input's folder and the input, e.g. `a_take1`.
+    Parent,
+    /// The name of the input and a hash of its content, e.g. `take1_3f2a9c1e`.
+    Hash,
+    /// A template with `{stem}`, `{parent}` and `{hash}` placeholders.
+    Template(String),
+}
+
+impl SceneNaming {
+    /// Parses `stem`, `parent`, `hash` or a template such as `{parent}_{stem}`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "stem" => Ok(SceneNaming::Stem),
+            "parent" => Ok(SceneNaming::Parent),
+            "hash" => Ok(SceneNaming::Hash),
+            template if template.contains('{') => {
+                let mut rest = template;
+                while let Some(start) = rest.find('{') {
+                    let Some(end) = rest[start..].find('}') else {
+                        return Err(format!("unclosed placeholder in `{}`", template));
+                    };
+                    let placeholder = &rest[start + 1..start + end];
+                    if !["stem", "parent", "hash"].contains(&placeholder) {
+                        return Err(format!(
+                            "unknown placeholder `{{{}}}`, expected {{stem}}, {{parent}} or {{hash}}",
+                            placeholder
+                        ));
+                    }
+                    rest = &rest[start + end + 1..];
+                }
+                Ok(SceneNaming::Template(template.to_string()))
+            }
+            _ => Err(format!(
+                "`{}` is not stem, parent, hash or a template such as {{parent}}_{{stem}}",
+                s
+            )),
+        }
+    }
+
+    fn template(&self) -> &str {
+        match self {
+            SceneNaming::Stem => "{stem}",
+            SceneNaming::Parent => "{parent}_{stem}",
+            SceneNaming::Hash => "{stem}_{hash}",
+            SceneNaming::Template(template) => template,
+        }
+    }
+
+    /// Returns the name of the scene built from `input`.
+    fn name(&self, input: &Path) -> Result<String> {
+        let template = self.template();
+        let mut name = template
+            .replace("{stem}", &scene_name(input))
+            .replace("{parent}", &dir_name(&parent_dir(input)));
+        if template.contains("{hash}") {
+            name = name.replace("{hash}", &content_hash(input)?);
+        }
+        if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+            bail!(
+                "`{}` is not a valid scene name for {}",
+                name,
+                input.display()
+            );
+        }
+        Ok(name)
+    }
+}
+
+/// Groups the positional inputs into scenes: one scene per input, or a single
+/// scene named `merge_into` combining all of them.
+///
+/// # Arguments
+///
+/// * `inputs` - The videos, image folders and image sequences to process.
+/// * `merge_into` - The name of the scene combining all inputs, if any.
+/// * `naming` - How the scene names are derived from the inputs.
+///
+/// # Returns
+///
+/// The scenes to process, or an error listing the inputs whose scenes would
+/// share a directory.
+pub fn plan_scenes(
+    inputs: &[PathBuf],
+    merge_into: Option<&str>,
+    naming: &SceneNaming,
+) -> Result<Vec<SceneInput>> {
+    if let Some(name) = merge_into {
+        return Ok(vec![SceneInput {
+            name: name.to_string(),
+            sources: inputs.to_vec(),
+            merged: true,
+        }]);
+    }
+
+    let mut scenes = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        scenes.push(SceneInput {
+            name: naming.name(input)?,
+            sources: vec![input.clone()],
+            merged: false,
+        });
+    }
+
+    // Scene directories on Windows do not differ by case alone.
+    let mut by_name: HashMap<String, Vec<&SceneInput>> = HashMap::new();
+    for scene in &scenes {
+        by_name
+            .entry(scene.name.to_lowercase())
+            .or_default()
+            .push(scene);
+    }
+    let mut collisions: Vec<String> = by_name
+        .values()
+        .filter(|scenes| scenes.len() > 1)
+        .map(|scenes| {
+            let inputs: Vec<String> = scenes.iter().map(|scene| scene.describe()).collect();
+            format!(
+                "  {} would all become scene '{}'",
+                inputs.join(", "),
+                scenes[0].name
+            )
+        })
+        .collect();
+    if !collisions.is_empty() {
+        collisions.sort();
+        bail!(
+            "Several inputs have the same scene name:\n{}\nChoose other names with --scene-name parent, --scene-name hash or a template such as --scene-name \"{{parent}}_{{stem}}\".",
+            collisions.join("\n")
+        );
+    }
+    Ok(scenes)
+}
+
+/// Number of bytes hashed at the start and at the end of a video.
+const HASH_SAMPLE_BYTES: u64 = 1 << 20;
+
+/// Returns a short hash identifying the content of an input.
+///
+/// Hashing whole videos would take as long as reading them, so a video is hashed
+/// by its size and its first and last megabyte. An image folder or sequence is
+/// hashed by the names and sizes of its images.
+fn content_hash(input: &Path) -> Result<String> {
+    let mut hash = Fnv1a::default();
+    if is_image_input(input) {
+        for image in list_images(input)? {
+            hash.write(image.file_name().unwrap_or_default().as_encoded_bytes());
+            hash.write(&fs::metadata(&image)?.len().to_le_bytes());
+        }
+    } else {
+        let mut file =
+            File::open(input).with_context(|| format!("Failed to open {}", input.display()))?;
+        let len = file.metadata()?.len();
+        hash.write(&len.to_le_bytes());
+        let mut buffer = Vec::new();
+        file.by_ref()
+            .take(HASH_SAMPLE_BYTES)
+            .read_to_end(&mut buffer)?;
+        if len > HASH_SAMPLE_BYTES {
+            file.seek(SeekFrom::Start(
+                len.saturating_sub(HASH_SAMPLE_BYTES).max(HASH_SAMPLE_BYTES),
+            ))?;
+            file.read_to_end(&mut buffer)?;
+        }
+        hash.write(&buffer);
+    }
+    Ok(format!("{:08x}", hash.0 >> 32))
+}
+
+/// The 64-bit FNV-1a hash. Unlike the standard library's hasher it is the same in
+/// every build, so scene names stay stable for `--resume`.
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+/// Returns whether `path` is a folder of images or an image sequence pattern
+/// rather than a video.
+pub fn is_image_input(path: &Path) -> bool {
+    path.is_dir() || is_pattern(path)
+}
+
+/// Returns whether `path` is an image sequence pattern. An existing file is never
+/// a pattern, even if its name contains a wildcard character.
+fn is_pattern(path: &Path) -> bool {
+    !path.is_file()
+        && path
+            .file_name()
+            .map(|name| name.to_string_lossy().contains(['#', '*', '?']))
+            .unwrap_or(false)
+}
+
+/// Returns the name of the scene built from an input: the file name of a video
+/// without its extension, the name of a folder, or the fixed part of a pattern's
+/// file name (`shot` for `shot_####.png`).
+pub fn scene_name(path: &Path) -> String {
+    if path.is_dir() {
+        return dir_name(path);
+    }
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if !is_pattern(path) {
+        return stem;
+    }
+    let fixed: String = stem.chars().filter(|c| !"#*?".contains(*c)).collect();
+    let fixed = fixed.trim_matches(|c: char| "_-. ".contains(c));
+    if fixed.is_empty() {
+        dir_name(&parent_dir(path))
+    } else {
+        fixed.to_string()
+    }
+}
+
+/// Returns the name of a directory, resolving `.` and `..` if needed.
+fn dir_name(dir: &Path) -> String {
+    let named = dir
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned());
+    named
+        .or_else(|| {
+            let absolute = std::path::absolute(dir).ok()?;
+            let canonical = absolute.canonicalize().unwrap_or(absolute);
+            Some(canonical.file_name()?.to_string_lossy().into_owned())
+        })
+        .unwrap_or_else(|| "images".to_string())
+}
+
+fn parent_dir(path: &Path) -> PathBuf {
+    path.parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+        .to_path_buf()
+}
+
+/// Lists the images of a folder or an image sequence, sorted by file name.
+pub fn list_images(path: &Path) -> Result<Vec<PathBuf>> {
+    let (dir, pattern) = if path.is_dir() {
+        (path.to_path_buf(), None)
+    } else {
+        let pattern = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        (parent_dir(path), Some(pattern))
+    };
+
+    let mut images = Vec::new();
+    let entries =
+        fs::read_dir(&dir).with_context(|| format!("Failed to read {}", dir.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let selected = match &pattern {
+            Some(pattern) => matches_pattern(pattern, name, true),
+            None => has_extension(&path, &IMAGE_EXTENSIONS),
+        };
+        if selected && path.is_file() {
+            images.push(path);
+        }
+    }
+    if images.is_empty() {
+        bail!("No images found for {}", path.display());
+    }
+    images.sort();
+    Ok(images)
+}
+
+/// Matches a file name against a pattern with `?` and `*` wildcards, ignoring
+/// case.
+///
+/// # Arguments
+///
+/// * `pattern` - The pattern, e.g. `shot_####.png` or `*nocuda*`.
+/// * `name` - The file name.
+/// * `digits` - Whether `#` matches a single digit, as in image sequences.
+///   Otherwise it only matches itself, like every other character.
+pub fn matches_pattern(pattern: &str, name: &str, digits: bool) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    matches(&pattern, &name, digits)
+}
+
+/// Matches with two pointers, remembering the position of the last `*` and how
+/// much of the name it swallowed. On a mismatch the `*` takes one more character
+/// and matching resumes after it, so a name is scanned in O(pattern × name) steps
+/// whatever the number of stars.
+fn matches(pattern: &[char], name: &[char], digits: bool) -> bool {
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if char_matches(c, name[n], digits) => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn char_matches(p: char, c: char, digits: bool) -> bool {
+    match p {
+        '#' if digits => c.is_ascii_digit(),
+        '?' => true,
+        _ => p.to_lowercase().eq(c.to_lowercase()),
+    }
+}
+
+/// Returns whether the extension of `path` is one of `extensions`, ignoring case.
+pub fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| extensions.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// Returns whether COLMAP can read an image as it is.
+pub fn colmap_can_read(path: &Path) -> bool {
+    has_extension(path, &COLMAP_EXTENSIONS)
+}
+
+/// Copies or symlinks images into `images_dir` as `frame_000001.<ext>`, ... so
+/// they are named like the frames extracted from a video.
+///
+/// # Arguments
+///
+/// * `images` - The images to import, in frame order.
+/// * `images_dir` - The images directory of the scene.
+/// * `symlink` - Whether to link to the images instead of copying them.
+pub fn import_images(images: &[PathBuf], images_dir: &Path, symlink: bool) -> Result<()> {
+    for (i, image) in images.iter().enumerate() {
+        let extension = match image.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("jpeg") => "jpg".to_string(),
+            Some(ext) => ext.to_string_lossy().to_ascii_lowercase(),
+            None => String::new(),
+        };
+        let frame = images_dir.join(format!("frame_{:06}.{}", i + 1, extension));
+        if symlink {
+            let target = std::path::absolute(image)?;
+            symlink_file(&target, &frame).with_context(|| {
+                format!(
+                    "Failed to link {} to {} (use --import-mode copy if symbolic links are not allowed)",
+                    frame.display(),
+                    target.display()
+                )
+            })?;
+        } else {
+            fs::copy(image, &frame).with_context(|| {
+                format!("Failed to copy {} to {}", image.display(), frame.display())
+            })?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn symlink_file(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+#[cfg(not(windows))]
+fn symlink_file(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+/// Writes an ffmpeg concat list that reads `images` one after another.
+pub fn write_concat_list(images: &[PathBuf], list_path: &Path) -> Result<()> {
+    let mut list = String::from("ffconcat version 1.0\n");
+    for image in images {
+        let path = std::path::absolute(image)?;
+        // Single quotes are closed, escaped and reopened.
+        let quoted = path.to_string_lossy().replace('\'', r"'\''");
+        list.push_str(&format!("file '{}'\n", quoted));
+    }
+    fs::write(list_path, list).with_context(|| format!("Failed to write {}", list_path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn existing_file_with_wildcard_in_name_is_a_video() {
+        let dir = std::env::temp_dir().join(format!("polyfjord3d-input-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let video = dir.join("take#1.mp4");
+        fs::write(&video, b"").unwrap();
+
+        assert!(!is_image_input(&video));
+        assert_eq!(scene_name(&video), "take#1");
+        assert!(is_image_input(&dir.join("shot_####.png")));
+        assert_eq!(scene_name(&dir.join("shot_####.png")), "shot");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn image_sequence_patterns_select_matching_files() {
+        let dir = std::env::temp_dir().join(format!("polyfjord3d-sequence-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in [
+            "shot_0002.png",
+            "SHOT_0001.PNG",
+            "shot_01.png",
+            "shot_000a.png",
+            "take_0001.png",
+        ] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+        let names = |pattern: &str| -> Vec<String> {
+            list_images(&dir.join(pattern))
+                .unwrap()
+                .iter()
+                .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+                .collect()
+        };
+
+        assert_eq!(names("shot_####.png"), ["SHOT_0001.PNG", "shot_0002.png"]);
+        assert_eq!(
+            names("shot_000?.png"),
+            ["SHOT_0001.PNG", "shot_0002.png", "shot_000a.png"]
+        );
+        assert_eq!(names("*_0001.png"), ["SHOT_0001.PNG", "take_0001.png"]);
+        assert_eq!(names("shot_##.png"), ["shot_01.png"]);
+        assert!(list_images(&dir.join("shot_#####.png")).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parses_scene_naming() {
+        assert_eq!(SceneNaming::parse("stem"), Ok(SceneNaming::Stem));
+        assert_eq!(SceneNaming::parse("parent"), Ok(SceneNaming::Parent));
+        assert_eq!(SceneNaming::parse("hash"), Ok(SceneNaming::Hash));
+        assert_eq!(
+            SceneNaming::parse("{parent}-{stem}"),
+            Ok(SceneNaming::Template("{parent}-{stem}".to_string()))
+        );
+        assert!(SceneNaming::parse("{name}").is_err());
+        assert!(SceneNaming::parse("{stem").is_err());
+        assert!(SceneNaming::parse("folder").is_err());
+    }
+
+    #[test]
+    fn rejects_scene_names_that_are_not_a_directory_name() {
+        let input = Path::new("a/take1.mp4");
+        let template = |s: &str| SceneNaming::Template(s.to_string());
+        assert_eq!(template("{stem}").name(input).unwrap(), "take1");
+        assert!(template("{parent}/{stem}").name(input).is_err());
+        assert!(template("{parent}\\{stem}").name(input).is_err());
+        assert!(template("{stem}")
+            .name(Path::new("shot_####.png"))
+            .is_ok_and(|name| name == "shot"));
+    }
+
+    #[test]
+    fn detects_inputs_with_the_same_scene_name() {
+        let inputs = [PathBuf::from("a/take1.mp4"), PathBuf::from("b/TAKE1.mov")];
+
+        let error = plan_scenes(&inputs, None, &SceneNaming::Stem).unwrap_err();
+        assert!(error.to_string().contains("a/take1.mp4, b/TAKE1.mov"));
+
+        let scenes = plan_scenes(&inputs, None, &SceneNaming::Parent).unwrap();
+        let names: Vec<&str> = scenes.iter().map(|scene| scene.name.as_str()).collect();
+        assert_eq!(names, ["a_take1", "b_TAKE1"]);
+
+        let merged = plan_scenes(&inputs, Some("both"), &SceneNaming::Stem).unwrap();
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].name, "both");
+    }
+
+    #[test]
+    fn hash_is_a_digit_only_in_image_sequences() {
+        assert!(matches_pattern("shot_##.PNG", "SHOT_01.png", true));
+        assert!(!matches_pattern("shot_##.png", "shot_01.png", false));
+        assert!(matches_pattern("build#*", "build#1-win64.zip", false));
+        assert!(!matches_pattern("build#*", "build#1-win64.zip", true));
+    }
+
+    #[test]
+    fn asset_globs_match_star_and_question_mark() {
+        assert!(matches_pattern(
+            "*nocuda*",
+            "colmap-x64-windows-NOCUDA.zip",
+            false
+        ));
+        assert!(!matches_pattern(
+            "*nocuda*",
+            "colmap-x64-windows-cuda.zip",
+            false
+        ));
+        assert!(matches_pattern(
+            "glomap-?.?.?-*.zip",
+            "glomap-1.1.0-windows.zip",
+            false
+        ));
+        assert!(!matches_pattern("glomap-?.zip", "glomap-10.zip", false));
+    }
+
+    #[test]
+    fn many_stars_do_not_backtrack_exponentially() {
+        let name = "a".repeat(10_000);
+        assert!(!matches_pattern("*a*a*a*a*a*a*a*a*b", &name, false));
+        assert!(matches_pattern("*a*a*a*a*a*a*a*a*", &name, false));
+        assert!(matches_pattern("*a**?*a", "xaya", false));
+        assert!(!matches_pattern("*a**?*a", "aa", false));
+        assert!(!matches_pattern("a*", "", false));
+        assert!(matches_pattern("**", "", false));
+    }
+}
@@ -0,0 +1,232 @@
+//! Logs of the external commands run for a scene.
+//!
+//! Every command writes its command line, environment overrides, output, duration
+//! and exit code to `logs/<step>.log` in the scene directory (`logs/mapping.<tool>.log`
+//! for the mapper attempts of `--fallback-tool`), and appends the same
+//! information, minus the full output, as one JSON line to `run.jsonl`. The logs
+//! make it possible to find out why a scene failed after the terminal is closed.
+
+use crate::state::Step;
+use crate::tool_output::Stream;
+use crate::CommandFailed;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Directory in the scene directory holding one log file per step.
+pub const LOGS_DIR: &str = "logs";
+/// File in the scene directory with one JSON line per command.
+pub const JOURNAL_FILE: &str = "run.jsonl";
+
+/// Number of stderr lines kept in the journal.
+const STDERR_TAIL_LINES: usize = 20;
+
+/// Returns the program and the arguments of a command.
+pub fn command_line(command: &Command) -> Vec<String> {
+    let mut line = vec![command.get_program().to_string_lossy().into_owned()];
+    line.extend(command.get_args().map(|a| a.to_string_lossy().into_owned()));
+    line
+}
+
+/// A line of `run.jsonl`.
+#[derive(Serialize, Debug)]
+pub struct JournalEntry {
+    /// Name of the scene.
+    pub scene: String,
+    /// The pipeline step that ran the command.
+    pub step: Step,
+    /// Name of the tool command, e.g. `feature_extractor`.
+    pub tool: String,
+    /// The program and its arguments.
+    pub command: Vec<String>,
+    /// Environment variables set (or removed, if `null`) for the command.
+    pub env: BTreeMap<String, Option<String>>,
+    /// Start of the command in seconds since the Unix epoch.
+    pub started_at: f64,
+    /// Run time of the command in seconds.
+    pub duration_secs: f64,
+    /// Exit code of the command, if it exited normally.
+    pub exit_code: Option<i32>,
+    /// Whether the command succeeded.
+    pub success: bool,
+    /// Why the command failed, if it did.
+    pub error: Option<String>,
+    /// The log file with the complete output.
+    pub log: PathBuf,
+    /// The last lines the command printed on stderr.
+    pub stderr_tail: Vec<String>,
+}
+
+/// The log of a command that is running.
+pub struct CommandLog {
+    entry: JournalEntry,
+    journal_path: PathBuf,
+    file: BufWriter<File>,
+    started: Instant,
+    stderr_tail: VecDeque<String>,
+}
+
+impl CommandLog {
+    /// Creates `logs/<log_name>.log` for a command about to run, replacing the log
+    /// of a previous run of the step.
+    ///
+    /// # Arguments
+    ///
+    /// * `scene_dir` - The scene directory.
+    /// * `scene` - The name of the scene.
+    /// * `step` - The pipeline step running the command.
+    /// * `log_name` - The name of the log file, usually the name of the step.
+    /// * `tool` - The name of the tool command.
+    /// * `command` - The command, with its arguments and environment set.
+    /// * `append` - Append to the log instead, for the later commands of a step
+    ///   that runs several.
+    ///
+    /// # Returns
+    ///
+    /// The log to pass every line of output to.
+    pub fn create(
+        scene_dir: &Path,
+        scene: &str,
+        step: Step,
+        log_name: &str,
+        tool: &str,
+        command: &Command,
+        append: bool,
+    ) -> Result<Self> {
+        let logs_dir = scene_dir.join(LOGS_DIR);
+        fs::create_dir_all(&logs_dir)?;
+        let log_path = logs_dir.join(format!("{}.log", log_name));
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(&log_path)
+            .with_context(|| format!("Failed to create {}", log_path.display()))?;
+
+        let command_line = command_line(command);
+        let env = command
+            .get_envs()
+            .map(|(key, value)| {
+                (
+                    key.to_string_lossy().into_owned(),
+                    value.map(|v| v.to_string_lossy().into_owned()),
+                )
+            })
+            .collect();
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or_default();
+
+        let mut log = CommandLog {
+            entry: JournalEntry {
+                scene: scene.to_string(),
+                step,
+                tool: tool.to_string(),
+                command: command_line,
+                env,
+                started_at,
+                duration_secs: 0.0,
+                exit_code: None,
+                success: false,
+                error: None,
+                log: log_path,
+                stderr_tail: Vec::new(),
+            },
+            journal_path: scene_dir.join(JOURNAL_FILE),
+            file: BufWriter::new(file),
+            started: Instant::now(),
+            stderr_tail: VecDeque::new(),
+        };
+        if append {
+            writeln!(log.file)?;
+        }
+        log.write_header()?;
+        Ok(log)
+    }
+
+    fn write_header(&mut self) -> Result<()> {
+        let entry = &self.entry;
+        writeln!(self.file, "step:       {}", entry.step)?;
+        writeln!(self.file, "command:    {}", entry.command.join(" "))?;
+        for (key, value) in &entry.env {
+            match value {
+                Some(value) => writeln!(self.file, "env:        {}={}", key, value)?,
+                None => writeln!(self.file, "env:        {} (removed)", key)?,
+            }
+        }
+        writeln!(self.file, "started at: {:.3}", entry.started_at)?;
+        writeln!(self.file)?;
+        self.file.flush()?;
+        Ok(())
+    }
+
+    /// Writes a line of output, prefixed with the stream it was printed on.
+    pub fn line(&mut self, stream: Stream, line: &str) -> Result<()> {
+        let prefix = match stream {
+            Stream::Stdout => "out",
+            Stream::Stderr => {
+                if self.stderr_tail.len() == STDERR_TAIL_LINES {
+                    self.stderr_tail.pop_front();
+                }
+                self.stderr_tail.push_back(line.to_string());
+                "err"
+            }
+        };
+        writeln!(self.file, "{}| {}", prefix, line)?;
+        Ok(())
+    }
+
+    /// Returns the last lines the command printed on stderr so far.
+    pub fn stderr_tail(&self) -> impl Iterator<Item = &str> {
+        self.stderr_tail.iter().map(String::as_str)
+    }
+
+    /// Returns the path of the log file.
+    pub fn path(&self) -> &Path {
+        &self.entry.log
+    }
+
+    /// Records how the command ended in the log file and appends the entry to
+    /// `run.jsonl`.
+    pub fn finish(mut self, result: &Result<()>) -> Result<JournalEntry> {
+        let entry = &mut self.entry;
+        entry.duration_secs = self.started.elapsed().as_secs_f64();
+        entry.success = result.is_ok();
+        match result {
+            Ok(()) => entry.exit_code = Some(0),
+            Err(e) => {
+                entry.exit_code = e
+                    .downcast_ref::<CommandFailed>()
+                    .and_then(|failed| failed.status.code());
+                entry.error = Some(format!("{:#}", e));
+            }
+        }
+        entry.stderr_tail = self.stderr_tail.into_iter().collect();
+
+        writeln!(self.file)?;
+        writeln!(self.file, "duration:   {:.3}s", entry.duration_secs)?;
+        match entry.exit_code {
+            Some(code) => writeln!(self.file, "exit code:  {}", code)?,
+            None => writeln!(self.file, "exit code:  none")?,
+        }
+        if let Some(error) = &entry.error {
+            writeln!(self.file, "error:      {}", error)?;
+        }
+        self.file.flush()?;
+
+        let mut journal = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.journal_path)
+            .with_context(|| format!("Failed to open {}", self.journal_path.display()))?;
+        writeln!(journal, "{}", serde_json::to_string(&self.entry)?)?;
+        Ok(self.entry)
+    }
+}
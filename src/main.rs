@@ -4,17 +4,45 @@
 //!
 //! Original credit: [Polyfjord](https://www.youtube.com/@Polyfjord)
 
-use anyhow::{anyhow, Context, Result};
-use clap::Parser;
+use anyhow::{anyhow, bail, Context, Result};
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
 use dirs::data_local_dir;
 use indicatif::{ProgressBar, ProgressStyle};
 use reqwest::blocking::Client;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::env;
+use std::ffi::OsString;
+use std::fmt;
 use std::fs::{self, File};
 use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::mpsc;
+
+mod colmap;
+mod config;
+mod export;
+mod input;
+mod journal;
+mod matcher;
+mod output;
+mod pipeline;
+mod report;
+mod scheduler;
+mod sharpness;
+mod state;
+mod tool_output;
+
+use config::{Config, DenseConfig, ExtractConfig, ImportMode, KeyframeConfig, Mesher};
+use export::ExportFormat;
+use input::{SceneInput, SceneNaming};
+use matcher::{Matcher, MatcherConfig, SequentialOptions, VocabTreeOptions};
+use output::{say, Event, OutputFormat};
+use pipeline::{plan_scene, process_scene, Layout, SubModels, Toolchain};
+use scheduler::Scheduler;
+use state::Step;
+use tool_output::Stream;
 
 /// GitHub repository for COLMAP.
 const COLMAP_REPO: &str = "colmap/colmap";
@@ -26,9 +54,14 @@ const FFMPEG_REPO: &str = "BtbN/FFmpeg-Builds";
 /// polyfjord3d command-line utility.
 /// This tool converts your videos into photogrammetry models - for 3D tracking in Blender 3D.
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None, disable_version_flag = true, color = clap::ColorChoice::Always, after_help = "Example:\n    polyfjord3d  video.mp4  video.mov")]
+#[command(author, version, about, long_about = None, disable_version_flag = true, color = clap::ColorChoice::Always, after_help = "Example:\n    polyfjord3d  video.mp4  video.mov  stills/  shots/shot_####.png\n    polyfjord3d  install colmap glomap ffmpeg --yes\n    polyfjord3d  bundle colmap glomap ffmpeg --yes --output bundle")]
+#[command(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
 struct Args {
-    /// List of video files to process.
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    /// List of video files, image folders or image sequences such as
+    /// `shots/shot_####.png` to process.
     #[arg(required = true)]
     videos: Vec<PathBuf>,
 
@@ -41,9 +74,99 @@ struct Args {
     scenes_dir: PathBuf,
 
     /// Force re-processing of existing scenes.
-    #[arg(long, short = 'f')]
+    #[arg(long, short = 'f', conflicts_with_all = ["resume", "from_step", "to_step"])]
     force: bool,
 
+    /// Resume existing scenes from the first pipeline step that did not finish.
+    #[arg(long, conflicts_with = "from_step")]
+    resume: bool,
+
+    /// Rerun existing scenes starting at this pipeline step.
+    #[arg(long, value_enum)]
+    from_step: Option<Step>,
+
+    /// Stop after this pipeline step.
+    #[arg(long, value_enum)]
+    to_step: Option<Step>,
+
+    /// Feature matcher to use. Defaults to sequential matching.
+    #[arg(long, value_enum)]
+    matcher: Option<Matcher>,
+
+    /// Enable loop detection for the sequential matcher (requires --vocab-tree-path).
+    #[arg(long)]
+    loop_detection: bool,
+
+    /// Path to a COLMAP vocabulary tree, used by loop detection and the vocab-tree matcher.
+    #[arg(long)]
+    vocab_tree_path: Option<PathBuf>,
+
+    /// Path to a polyfjord3d.toml config file. Defaults to the one next to each video.
+    #[arg(long, short = 'c')]
+    config: Option<PathBuf>,
+
+    /// How to arrange the files of each scene directory.
+    #[arg(long, value_enum, default_value_t = Layout::Colmap)]
+    layout: Layout,
+
+    /// Reconstruct all inputs together as one scene with this name. The frames of
+    /// each input go to their own subfolder of the images and get their own camera.
+    #[arg(long, value_name = "SCENE")]
+    merge_into: Option<String>,
+
+    /// How scene names are derived from the inputs: `stem` (the file name),
+    /// `parent` (`<folder>_<file name>`), `hash` (`<file name>_<content hash>`) or
+    /// a template with `{stem}`, `{parent}` and `{hash}` placeholders.
+    #[arg(long, default_value = "stem", value_parser = SceneNaming::parse, conflicts_with = "merge_into")]
+    scene_name: SceneNaming,
+
+    /// Compute a dense point cloud in `dense/` after the sparse reconstruction.
+    #[arg(long)]
+    dense: bool,
+
+    /// Mesh the dense point cloud with this mesher (needs --dense, ignored with a
+    /// warning otherwise).
+    #[arg(long, value_enum)]
+    mesher: Option<Mesher>,
+
+    /// Additional formats to export each reconstructed scene to.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    export: Vec<ExportFormat>,
+
+    /// What to do when the mapper splits a scene into several sub-models. They
+    /// are renumbered so `sparse/0` is always the largest.
+    #[arg(long, value_enum, default_value_t = SubModels::Largest)]
+    sub_models: SubModels,
+
+    /// Warn when a scene registers less than this fraction of its images.
+    #[arg(long, default_value_t = 0.5, value_parser = parse_ratio)]
+    min_registration_ratio: f64,
+
+    /// If the mapper fails or registers less than --min-registration-ratio, rerun
+    /// the mapping step with the other tool and keep the better model.
+    #[arg(long)]
+    fallback_tool: bool,
+
+    /// Treat scenes below --min-registration-ratio as failed and exit with a
+    /// nonzero code if any scene failed.
+    #[arg(long)]
+    strict: bool,
+
+    /// Print the scenes and the commands that would run for them, without running
+    /// anything or downloading missing tools.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Number of videos to process at the same time. GPU-heavy and CPU-heavy
+    /// steps still run for one video at a time.
+    #[arg(long, short = 'j', default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    jobs: u32,
+
+    /// Format of the output on stdout. `json` prints one JSON event per line and
+    /// moves the human-readable messages to stderr.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    output_format: OutputFormat,
+
     /// Path to ffmpeg executable.
     #[arg(long)]
     ffmpeg_path: Option<PathBuf>,
@@ -52,13 +175,191 @@ struct Args {
     #[arg(long)]
     tool_path: Option<PathBuf>,
 
+    #[command(flatten)]
+    download: DownloadOptions,
+
+    #[command(flatten)]
+    sampling: FrameSampling,
+
+    /// Keep only the frames that are the sharpest of some N consecutive extracted
+    /// frames (a sliding window).
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(2..))]
+    keyframe_window: Option<u32>,
+
+    /// How the frames of image folders and sequences are brought into the scene.
+    /// Defaults to copying them.
+    #[arg(long, value_enum)]
+    import_mode: Option<ImportMode>,
+
     /// Print version information.
     #[arg(short = 'v', long = "version", action = clap::ArgAction::Version)]
     version_flag: Option<bool>,
 }
 
+impl Args {
+    /// Rejects a command written after a video or an option, such as
+    /// `polyfjord3d video.mp4 install colmap`. Since options and videos conflict
+    /// with commands, clap takes the command for another video.
+    fn reject_misplaced_command(&self) {
+        let mut command = Args::command();
+        let misplaced = self.videos.iter().find_map(|video| {
+            command
+                .get_subcommands()
+                .map(|sub| sub.get_name())
+                .find(|&name| video.as_os_str() == name && !video.exists())
+        });
+        if let Some(name) = misplaced {
+            let message = format!(
+                "'{}' is a command and cannot be combined with videos or options before it - run it on its own, e.g. `polyfjord3d {} colmap --yes`",
+                name, name
+            );
+            command.error(ErrorKind::ArgumentConflict, message).exit();
+        }
+    }
+
+    /// Returns whether the selected steps run COLMAP or GLOMAP. Exporting an
+    /// existing model only needs the native model reader.
+    fn needs_reconstruction_tools(&self, scenes: &[SceneInput]) -> bool {
+        let first = self.from_step.unwrap_or(Step::Extract);
+        let last = self.to_step.unwrap_or(Step::Export);
+        let dense = self.dense_enabled(scenes);
+        self.resume
+            || Step::ALL.into_iter().any(|step| {
+                (first..=last).contains(&step)
+                    && match step {
+                        Step::Features | Step::Matching | Step::Mapping => true,
+                        Step::DenseUndistort | Step::Stereo | Step::Fusion | Step::Meshing => dense,
+                        Step::Undistort => self.layout == Layout::GaussianSplatting,
+                        _ => false,
+                    }
+            })
+    }
+
+    /// Returns whether any scene computes a dense point cloud, because of --dense
+    /// or the `[dense]` section of its config file. Config files that fail to
+    /// parse are reported when their scene starts.
+    fn dense_enabled(&self, scenes: &[SceneInput]) -> bool {
+        self.dense
+            || scenes.iter().any(|scene| {
+                config::find_config_file(self.config.as_deref(), &scene.sources[0])
+                    .and_then(|path| Config::from_file(&path).ok())
+                    .is_some_and(|config| config.dense.enabled == Some(true))
+            })
+    }
+
+    /// Returns the pipeline options set on the command line, which take priority
+    /// over the config file.
+    fn config_overrides(&self) -> Config {
+        Config {
+            extract: ExtractConfig {
+                fps: self.sampling.fps,
+                every_nth_frame: self.sampling.every_nth_frame,
+                max_frames: self.sampling.max_frames,
+                qscale: None,
+                import: self.import_mode,
+            },
+            keyframes: KeyframeConfig {
+                window: self.keyframe_window,
+            },
+            matcher: MatcherConfig {
+                strategy: self.matcher,
+                sequential: SequentialOptions {
+                    loop_detection: self.loop_detection.then_some(true),
+                    vocab_tree_path: self.vocab_tree_path.clone(),
+                    ..Default::default()
+                },
+                vocab_tree: VocabTreeOptions {
+                    vocab_tree_path: self.vocab_tree_path.clone(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            dense: DenseConfig {
+                enabled: self.dense.then_some(true),
+                mesher: self.mesher,
+            },
+            ..Default::default()
+        }
+    }
+}
+
+/// Commands other than processing videos.
+#[derive(clap::Subcommand, Debug)]
+enum Commands {
+    /// Download and install tools without processing any video. Tools already
+    /// found in PATH or in the install directory are left as they are.
+    Install {
+        /// The tools to install.
+        #[arg(required = true, value_enum)]
+        tools: Vec<Dependency>,
+    },
+    /// Download the release archives of tools into a directory, to install them
+    /// on machines without internet access with `--offline-bundle`.
+    Bundle {
+        /// The tools to download.
+        #[arg(required = true, value_enum)]
+        tools: Vec<Dependency>,
+
+        /// Directory to write the archives to.
+        #[arg(long, short = 'o', default_value = "polyfjord3d-bundle")]
+        output: PathBuf,
+    },
+}
+
+/// Options for downloading missing tools from their GitHub releases.
+#[derive(clap::Args, Debug, Clone)]
+struct DownloadOptions {
+    /// Download missing tools without asking which package to install: a CUDA
+    /// build if the release has one and an NVIDIA driver is installed, the build
+    /// without CUDA otherwise.
+    #[arg(long, short = 'y', global = true)]
+    yes: bool,
+
+    /// Download the package whose file name matches this glob, ignoring case:
+    /// `*` matches any text and `?` any single character, e.g. `*nocuda*`.
+    #[arg(long, value_name = "GLOB", global = true)]
+    asset_pattern: Option<String>,
+
+    /// Install missing tools from local release archives instead of downloading
+    /// them: the `.zip` of a tool, or a directory of archives such as the one
+    /// `polyfjord3d bundle` writes. GitHub is never contacted.
+    #[arg(long, value_name = "PATH", global = true)]
+    offline_bundle: Option<PathBuf>,
+}
+
+/// Options controlling which frames ffmpeg extracts from a video.
+#[derive(clap::Args, Debug, Clone)]
+struct FrameSampling {
+    /// Extract frames at this rate (frames per second) instead of every frame.
+    #[arg(long, conflicts_with = "every_nth_frame", value_parser = parse_fps)]
+    fps: Option<f64>,
+
+    /// Extract only every Nth frame of the video.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    every_nth_frame: Option<u32>,
+
+    /// Stop after extracting this many frames.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    max_frames: Option<u32>,
+}
+
+fn parse_fps(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(fps) if fps > 0.0 && fps.is_finite() => Ok(fps),
+        _ => Err(format!("`{}` is not a positive frame rate", s)),
+    }
+}
+
+fn parse_ratio(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(ratio) if (0.0..=1.0).contains(&ratio) => Ok(ratio),
+        _ => Err(format!("`{}` is not a ratio between 0 and 1", s)),
+    }
+}
+
 /// Enum representing the available photogrammetry tools.
-#[derive(clap::ValueEnum, Clone, Debug, Copy)]
+#[derive(clap::ValueEnum, Serialize, Deserialize, Clone, Debug, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
 enum Tool {
     /// Use COLMAP for reconstruction.
     Colmap,
@@ -66,6 +367,60 @@ enum Tool {
     Glomap,
 }
 
+impl Tool {
+    /// Name of the tool's executable and of its install directory.
+    fn name(self) -> &'static str {
+        match self {
+            Tool::Colmap => "colmap",
+            Tool::Glomap => "glomap",
+        }
+    }
+
+    /// GitHub repository the tool is downloaded from.
+    fn repo(self) -> &'static str {
+        match self {
+            Tool::Colmap => COLMAP_REPO,
+            Tool::Glomap => GLOMAP_REPO,
+        }
+    }
+
+    /// The tool used by `--fallback-tool` when this one fails.
+    fn other(self) -> Tool {
+        match self {
+            Tool::Colmap => Tool::Glomap,
+            Tool::Glomap => Tool::Colmap,
+        }
+    }
+}
+
+/// A tool polyfjord3d can download and install.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Dependency {
+    Ffmpeg,
+    Colmap,
+    Glomap,
+}
+
+impl Dependency {
+    /// Name of the tool's executable and of its install directory.
+    fn name(self) -> &'static str {
+        match self {
+            Dependency::Ffmpeg => "ffmpeg",
+            Dependency::Colmap => "colmap",
+            Dependency::Glomap => "glomap",
+        }
+    }
+
+    /// GitHub repository the tool is downloaded from.
+    fn repo(self) -> &'static str {
+        match self {
+            Dependency::Ffmpeg => FFMPEG_REPO,
+            Dependency::Colmap => COLMAP_REPO,
+            Dependency::Glomap => GLOMAP_REPO,
+        }
+    }
+}
+
 /// Represents a GitHub release.
 #[derive(Deserialize, Debug)]
 struct Release {
@@ -140,68 +495,52 @@ fn unzip_file(path: &Path, dest: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Returns the directory tools are installed into. It is only created when a tool
+/// is installed, so `--dry-run` leaves it alone.
 fn get_install_dir() -> Result<PathBuf> {
-    let dir = data_local_dir()
+    Ok(data_local_dir()
         .ok_or_else(|| anyhow!("Failed to get local data directory"))?
-        .join("polyfjord3d");
-    if !dir.exists() {
-        fs::create_dir_all(&dir)?;
-    }
-    Ok(dir)
+        .join("polyfjord3d"))
 }
 
-fn prompt_and_download_tool(tool_name: &str, repo: &str, dest_dir: &Path) -> Result<PathBuf> {
-    println!(
+fn prompt_and_download_tool(
+    tool_name: &str,
+    repo: &str,
+    dest_dir: &Path,
+    download: &DownloadOptions,
+) -> Result<PathBuf> {
+    say!(
         "[INFO] {} not found in PATH or at ({})",
         tool_name,
         dest_dir.display()
     );
-    println!("[INFO] Fetching latest releases from GitHub...");
+    say!("[INFO] Fetching latest releases from GitHub...");
 
     let release = get_latest_release(repo)?;
-    println!("[INFO] Latest release is {}", release.tag_name);
+    say!("[INFO] Latest release is {}", release.tag_name);
 
-    let mut downloadable_assets: Vec<Asset> = release
-        .assets
-        .into_iter()
-        .filter(|a| a.name.contains("win") && a.name.ends_with(".zip"))
-        .collect();
-
-    if downloadable_assets.is_empty() {
-        return Err(anyhow!("No suitable Windows .zip assets found in the latest release. Please install {} manually.", tool_name));
-    }
-
-    println!("Please choose a package to download:");
-    for (i, asset) in downloadable_assets.iter().enumerate() {
-        println!("[{}] {}", i + 1, asset.name);
-    }
-
-    let choice: usize = loop {
-        print!("> ");
-        io::stdout().flush()?;
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
-        match input.trim().parse::<usize>() {
-            Ok(n) if n > 0 && n <= downloadable_assets.len() => break n - 1,
-            _ => println!("Invalid choice. Please enter a number from the list."),
-        }
-    };
+    let mut downloadable_assets = windows_assets(release, tool_name)?;
 
+    let names: Vec<&str> = downloadable_assets
+        .iter()
+        .map(|a| a.name.as_str())
+        .collect();
+    let choice = choose_package(tool_name, &names, download)?;
     let asset = downloadable_assets.remove(choice);
     let download_url = asset.browser_download_url;
     let file_name = asset.name;
     let zip_path = dest_dir.join(&file_name);
 
-    println!("[INFO] Downloading {}...", file_name);
+    say!("[INFO] Downloading {}...", file_name);
     download_file(&download_url, &zip_path)?;
 
-    println!("[INFO] Unzipping {}...", file_name);
+    say!("[INFO] Unzipping {}...", file_name);
     unzip_file(&zip_path, dest_dir)?;
 
-    println!("[INFO] Cleaning up downloaded archive...");
+    say!("[INFO] Cleaning up downloaded archive...");
     fs::remove_file(&zip_path)?;
 
-    println!("[INFO] {} installed successfully.", tool_name);
+    say!("[INFO] {} installed successfully.", tool_name);
 
     find_executable(dest_dir, tool_name).ok_or_else(|| {
         anyhow!(
@@ -211,6 +550,263 @@ fn prompt_and_download_tool(tool_name: &str, repo: &str, dest_dir: &Path) -> Res
     })
 }
 
+/// Installs a tool from a release archive of an offline bundle, without contacting
+/// GitHub.
+///
+/// # Arguments
+///
+/// * `tool_name` - The tool to install.
+/// * `bundle` - A `.zip` release archive of the tool, or a directory of release
+///   archives whose file names contain the names of their tools.
+/// * `dest_dir` - The install directory of the tool.
+/// * `download` - The download options, used to choose between several archives.
+fn install_from_bundle(
+    tool_name: &str,
+    bundle: &Path,
+    dest_dir: &Path,
+    download: &DownloadOptions,
+) -> Result<PathBuf> {
+    say!(
+        "[INFO] {} not found in PATH or at ({})",
+        tool_name,
+        dest_dir.display()
+    );
+    let archives = bundle_archives(bundle, tool_name)?;
+    if archives.is_empty() {
+        bail!(
+            "The offline bundle {} has no release archive of {}",
+            bundle.display(),
+            tool_name
+        );
+    }
+    let names: Vec<String> = archives
+        .iter()
+        .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+        .collect();
+    let names: Vec<&str> = names.iter().map(String::as_str).collect();
+    let archive = &archives[choose_package(tool_name, &names, download)?];
+
+    say!("[INFO] Unzipping {}...", archive.display());
+    unzip_file(archive, dest_dir)?;
+
+    say!("[INFO] {} installed successfully.", tool_name);
+
+    find_executable(dest_dir, tool_name).ok_or_else(|| {
+        anyhow!(
+            "Failed to find {} executable after installation.",
+            tool_name
+        )
+    })
+}
+
+/// Returns the release archives of a tool in an offline bundle: the bundle itself
+/// if it is a `.zip` of the tool, or the `.zip` files of the tool in the bundle
+/// directory. Archives belong to the tools whose names they contain.
+fn bundle_archives(bundle: &Path, tool_name: &str) -> Result<Vec<PathBuf>> {
+    let is_archive = |path: &Path| {
+        input::has_extension(path, &["zip"])
+            && path.file_name().is_some_and(|name| {
+                name.to_string_lossy()
+                    .to_ascii_lowercase()
+                    .contains(tool_name)
+            })
+    };
+    if bundle.is_file() {
+        return Ok(if is_archive(bundle) {
+            vec![bundle.to_path_buf()]
+        } else {
+            Vec::new()
+        });
+    }
+    if !bundle.is_dir() {
+        bail!("Offline bundle does not exist: {}", bundle.display());
+    }
+
+    let mut archives = Vec::new();
+    let entries =
+        fs::read_dir(bundle).with_context(|| format!("Failed to read {}", bundle.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_file() && is_archive(&path) {
+            archives.push(path);
+        }
+    }
+    archives.sort();
+    Ok(archives)
+}
+
+/// Downloads the release archives of tools into `output`, for `polyfjord3d
+/// bundle`. Archives already in `output` are kept.
+fn build_bundle(tools: &[Dependency], output: &Path, download: &DownloadOptions) -> Result<()> {
+    fs::create_dir_all(output)?;
+    for &tool in tools {
+        say!(
+            "[INFO] Fetching latest release of {} from GitHub...",
+            tool.name()
+        );
+        let release = get_latest_release(tool.repo())?;
+        say!("[INFO] Latest release is {}", release.tag_name);
+
+        let assets = windows_assets(release, tool.name())?;
+        let names: Vec<&str> = assets.iter().map(|a| a.name.as_str()).collect();
+        for index in bundle_packages(tool.name(), &names, download)? {
+            let asset = &assets[index];
+            // Offline installs find the archive of a tool by its name.
+            let file_name = if asset.name.to_ascii_lowercase().contains(tool.name()) {
+                asset.name.clone()
+            } else {
+                format!("{}-{}", tool.name(), asset.name)
+            };
+            let path = output.join(&file_name);
+            if path.exists() {
+                say!("[INFO] {} is already in the bundle.", file_name);
+                continue;
+            }
+            say!("[INFO] Downloading {}...", file_name);
+            let partial_path = output.join(format!("{}.part", file_name));
+            download_file(&asset.browser_download_url, &partial_path)?;
+            fs::rename(&partial_path, &path)?;
+        }
+    }
+
+    let names: Vec<&str> = tools.iter().map(|tool| tool.name()).collect();
+    say!(
+        "[INFO] Bundle written to {}. Copy it to the offline machine and run:",
+        output.display()
+    );
+    say!(
+        "    polyfjord3d install {} --offline-bundle <bundle directory>",
+        names.join(" ")
+    );
+    Ok(())
+}
+
+/// Chooses the packages of a tool to put into a bundle.
+///
+/// The NVIDIA driver of this machine says nothing about the offline machine, so
+/// with `--yes` and without `--asset-pattern` both the CUDA build and the build
+/// without CUDA are bundled, and the install on the offline machine picks one.
+///
+/// # Returns
+///
+/// The indices of the chosen packages in `names`.
+fn bundle_packages(
+    tool_name: &str,
+    names: &[&str],
+    download: &DownloadOptions,
+) -> Result<Vec<usize>> {
+    if download.yes && download.asset_pattern.is_none() {
+        let mut chosen = vec![
+            preferred_package(names, true),
+            preferred_package(names, false),
+        ];
+        chosen.dedup();
+        return Ok(chosen);
+    }
+    Ok(vec![choose_package(tool_name, names, download)?])
+}
+
+/// Returns the Windows `.zip` assets of a release.
+fn windows_assets(release: Release, tool_name: &str) -> Result<Vec<Asset>> {
+    let assets: Vec<Asset> = release
+        .assets
+        .into_iter()
+        .filter(|a| a.name.contains("win") && a.name.ends_with(".zip"))
+        .collect();
+
+    if assets.is_empty() {
+        return Err(anyhow!("No suitable Windows .zip assets found in the latest release. Please install {} manually.", tool_name));
+    }
+    Ok(assets)
+}
+
+/// Chooses the package of a tool to install: the one matching `--asset-pattern`,
+/// the [`preferred_package`] with `--yes`, or the one the user picks.
+///
+/// # Arguments
+///
+/// * `tool_name` - The tool the packages belong to.
+/// * `names` - File names of the packages.
+/// * `download` - The download options.
+///
+/// # Returns
+///
+/// The index of the chosen package in `names`.
+fn choose_package(tool_name: &str, names: &[&str], download: &DownloadOptions) -> Result<usize> {
+    let mut candidates: Vec<usize> = (0..names.len()).collect();
+    if let Some(pattern) = &download.asset_pattern {
+        candidates.retain(|&i| input::matches_pattern(pattern, names[i], false));
+        if candidates.is_empty() {
+            bail!(
+                "No package of {} matches --asset-pattern {} (available: {})",
+                tool_name,
+                pattern,
+                names.join(", ")
+            );
+        }
+    }
+
+    if candidates.len() == 1 {
+        return Ok(candidates[0]);
+    }
+    if download.yes {
+        let candidate_names: Vec<&str> = candidates.iter().map(|&i| names[i]).collect();
+        return Ok(candidates[preferred_package(&candidate_names, has_cuda())]);
+    }
+
+    say!("Please choose a package to install:");
+    for (i, &candidate) in candidates.iter().enumerate() {
+        say!("[{}] {}", i + 1, names[candidate]);
+    }
+    loop {
+        output::print_prompt("> ")?;
+        let mut input = String::new();
+        if io::stdin().read_line(&mut input)? == 0 {
+            bail!(
+                "No package of {} was chosen - use --yes or --asset-pattern to install it without asking",
+                tool_name
+            );
+        }
+        match input.trim().parse::<usize>() {
+            Ok(n) if n > 0 && n <= candidates.len() => return Ok(candidates[n - 1]),
+            _ => say!("Invalid choice. Please enter a number from the list."),
+        }
+    }
+}
+
+/// Returns whether an NVIDIA driver is installed, which CUDA builds need to run.
+fn has_cuda() -> bool {
+    let driver = env::var_os("SystemRoot")
+        .map(|root| Path::new(&root).join("System32").join("nvcuda.dll"))
+        .is_some_and(|dll| dll.exists());
+    driver || which::which("nvidia-smi").is_ok()
+}
+
+/// Chooses a package without asking: a CUDA build if there is one and `cuda` says
+/// an NVIDIA driver is installed, otherwise a build without CUDA. Among equal
+/// candidates, x64 builds are preferred over ARM
+/// and 32-bit ones and static builds over `-shared` ones, and the order of
+/// `names` decides the rest.
+///
+/// # Returns
+///
+/// The index of the chosen package in `names`.
+fn preferred_package(names: &[&str], cuda: bool) -> usize {
+    names
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, name)| {
+            let name = name.to_ascii_lowercase();
+            let cuda_build = name.contains("cuda")
+                && !["nocuda", "no-cuda", "no_cuda"]
+                    .iter()
+                    .any(|no_cuda| name.contains(no_cuda));
+            let other_arch = name.contains("arm") || name.contains("win32");
+            (cuda_build != cuda, other_arch, name.contains("shared"))
+        })
+        .map_or(0, |(i, _)| i)
+}
+
 fn find_executable(dir: &Path, name: &str) -> Option<PathBuf> {
     let exe_name = format!("{}.exe", name);
     let primary_path = dir.join(&exe_name);
@@ -229,6 +825,8 @@ fn check_dependency(
     repo: &str,
     arg_path: Option<PathBuf>,
     install_dir_name: &str,
+    download: &DownloadOptions,
+    dry_run: bool,
 ) -> Result<(PathBuf, bool)> {
     if let Some(path) = arg_path {
         if path.exists() {
@@ -243,17 +841,13 @@ fn check_dependency(
     }
 
     if let Ok(path) = which::which(name) {
-        println!("[INFO] Found {} in PATH: {}", name, path.display());
+        say!("[INFO] Found {} in PATH: {}", name, path.display());
         return Ok((path, false));
     }
 
     let install_dir = get_install_dir()?.join(install_dir_name);
-    if !install_dir.exists() {
-        fs::create_dir_all(&install_dir)?;
-    }
-
     if let Some(path) = find_executable(&install_dir, name) {
-        println!(
+        say!(
             "[INFO] Found {} in {}: {}",
             name,
             install_dir_name,
@@ -262,250 +856,418 @@ fn check_dependency(
         return Ok((path, false));
     }
 
-    prompt_and_download_tool(name, repo, &install_dir).map(|path| (path, true))
-    // Err(anyhow!("{} not found. Please install it and ensure it's in your PATH, or place it in the install directory.", name))
-}
-
-fn run_command(command: &mut Command, video_name: &str, step_name: &str) -> Result<()> {
-    let output = command
-        .output()
-        .with_context(|| format!("Failed to execute {}", step_name))?;
+    if dry_run {
+        match &download.offline_bundle {
+            Some(bundle) => say!(
+                "[INFO] {} not found - it would be installed from {}.",
+                name,
+                bundle.display()
+            ),
+            None => say!(
+                "[INFO] {} not found - it would be downloaded from GitHub ({}).",
+                name,
+                repo
+            ),
+        }
+        return Ok((PathBuf::from(name), false));
+    }
+    if !install_dir.exists() {
+        fs::create_dir_all(&install_dir)?;
+    }
 
-    if !output.status.success() {
-        io::stderr().write_all(&output.stderr)?;
-        Err(anyhow!("{} failed for {}", step_name, video_name))
-    } else {
-        Ok(())
+    if let Some(bundle) = &download.offline_bundle {
+        return install_from_bundle(name, bundle, &install_dir, download).map(|path| (path, true));
     }
+
+    prompt_and_download_tool(name, repo, &install_dir, download).map(|path| (path, true))
+    // Err(anyhow!("{} not found. Please install it and ensure it's in your PATH, or place it in the install directory.", name))
 }
 
-/// Processes a single video file.
+/// Runs a command and waits for it to finish.
 ///
 /// # Arguments
 ///
-/// * `video_path` - The path to the video file.
-/// * `scenes_dir` - The directory to store the processed scenes.
-/// * `ffmpeg_path` - The path to the ffmpeg executable.
-/// * `tool_path` - The path to the photogrammetry tool executable.
-/// * `colmap_path` - The path to the COLMAP executable.
-/// * `tool` - The photogrammetry tool to use.
-/// * `force` - Whether to force re-processing of existing scenes.
+/// * `command` - The command to run.
+/// * `video_name` - The video the command runs for, used in error messages.
+/// * `step_name` - The name of the command, used in error messages.
+/// * `on_line` - Called with every line the command prints on stdout or stderr
+///   while it runs. An error stops the command.
 ///
 /// # Returns
 ///
-/// A `Result` indicating success or failure.
-fn process_video(
-    video_path: &Path,
-    scenes_dir: &Path,
-    ffmpeg_path: &Path,
-    tool_path: &Path,
-    colmap_path: &Path,
-    tool: Tool,
-    force: bool,
+/// An error if the command could not be started or did not succeed. The error of a
+/// command that did not succeed is a [`CommandFailed`]; its output is left to
+/// `on_line`.
+fn run_command(
+    command: &mut Command,
+    video_name: &str,
+    step_name: &str,
+    mut on_line: impl FnMut(Stream, &str) -> Result<()>,
 ) -> Result<()> {
-    let video_name = video_path.file_stem().unwrap().to_str().unwrap();
-    println!("\n=== Processing {} ===", video_name);
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to execute {}", step_name))?;
 
-    let scene_dir = scenes_dir.join(video_name);
-    let images_dir = scene_dir.join("images");
-    let sparse_dir = scene_dir.join("sparse");
+    let (sender, receiver) = mpsc::channel();
+    let readers = [
+        child
+            .stdout
+            .take()
+            .map(|out| tool_output::spawn_line_reader(out, Stream::Stdout, sender.clone())),
+        child
+            .stderr
+            .take()
+            .map(|err| tool_output::spawn_line_reader(err, Stream::Stderr, sender.clone())),
+    ];
+    drop(sender);
+
+    for (stream, line) in receiver {
+        if let Err(e) = on_line(stream, &line) {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(e);
+        }
+    }
+    for reader in readers.into_iter().flatten() {
+        let _ = reader.join();
+    }
 
-    if scene_dir.exists() {
-        if force {
-            println!("[INFO] Scene directory exists. Forcing overwrite.");
-            fs::remove_dir_all(&scene_dir)?;
-        } else {
-            println!("[INFO] Skipping {} - already processed.", video_name);
-            return Ok(());
+    let status = child
+        .wait()
+        .with_context(|| format!("Failed to wait for {}", step_name))?;
+    if !status.success() {
+        Err(CommandFailed {
+            step_name: step_name.to_string(),
+            video_name: video_name.to_string(),
+            status,
         }
+        .into())
+    } else {
+        Ok(())
     }
+}
 
-    fs::create_dir_all(&images_dir)?;
-    fs::create_dir_all(&sparse_dir)?;
+/// Error of a command that ran but did not succeed.
+#[derive(Debug)]
+struct CommandFailed {
+    step_name: String,
+    video_name: String,
+    status: ExitStatus,
+}
 
-    // 1. Extract frames from the video using ffmpeg.
-    println!("[1/4] Extracting frames...");
-    run_command(
-        Command::new(ffmpeg_path)
-            .arg("-i")
-            .arg(video_path)
-            .arg("-qscale:v")
-            .arg("2")
-            .arg(images_dir.join("frame_%06d.jpg")),
-        video_name,
-        "ffmpeg",
-    )?;
+impl fmt::Display for CommandFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} failed for {}", self.step_name, self.video_name)
+    }
+}
 
-    // 2. Run COLMAP feature extractor to detect keypoints in the images.
-    println!("[2/4] Feature extraction...");
-    let db_path = scene_dir.join("database.db");
-    run_command(
-        Command::new(colmap_path)
-            .arg("feature_extractor")
-            .arg("--database_path")
-            .arg(&db_path)
-            .arg("--image_path")
-            .arg(&images_dir)
-            .arg("--ImageReader.single_camera")
-            .arg("1")
-            .arg("--SiftExtraction.use_gpu")
-            .arg("1")
-            .arg("--SiftExtraction.max_image_size")
-            .arg("4096"),
-        video_name,
-        "feature_extractor",
-    )?;
+impl std::error::Error for CommandFailed {}
 
-    // 3. Run COLMAP sequential matcher to find corresponding features between images.
-    println!("[3/4] Feature matching...");
-    run_command(
-        Command::new(colmap_path)
-            .arg("sequential_matcher")
-            .arg("--database_path")
-            .arg(&db_path)
-            .arg("--SequentialMatching.overlap")
-            .arg("15"),
-        video_name,
-        "sequential_matcher",
-    )?;
+/// Returns the `QT_PLUGIN_PATH` COLMAP runs with: the plugins of the installed
+/// COLMAP, followed by the plugin directories already set.
+fn qt_plugin_path() -> Result<OsString> {
+    let plugins_path = get_install_dir()?.join("colmap").join("plugins");
+    let mut paths = vec![plugins_path];
+    if let Some(existing_path) = env::var_os("QT_PLUGIN_PATH") {
+        paths.extend(env::split_paths(&existing_path));
+    }
+    Ok(env::join_paths(paths)?)
+}
 
-    // 4. Perform sparse reconstruction to create a 3D point cloud.
-    println!("[4/4] Sparse reconstruction...");
-    let mut mapper_cmd = Command::new(tool_path);
-    mapper_cmd
-        .arg("mapper")
-        .arg("--database_path")
-        .arg(&db_path)
-        .arg("--image_path")
-        .arg(&images_dir)
-        .arg("--output_path")
-        .arg(&sparse_dir);
-
-    if let Tool::Colmap = tool {
-        let num_threads = num_cpus::get().to_string();
-        mapper_cmd.arg("--Mapper.num_threads").arg(num_threads);
-    }
-
-    run_command(&mut mapper_cmd, video_name, "mapper")?;
-
-    // Export the reconstructed model to a human-readable TXT format.
-    let model_path = sparse_dir.join("0");
-    if model_path.exists() {
-        println!("[INFO] Exporting model to TXT...");
-        if let Tool::Glomap = tool {
-            // For Glomap, the model needs to be converted twice.
-            run_command(
-                Command::new(colmap_path)
-                    .arg("model_converter")
-                    .arg("--input_path")
-                    .arg(&model_path)
-                    .arg("--output_path")
-                    .arg(&model_path)
-                    .arg("--output_type")
-                    .arg("TXT"),
-                video_name,
-                "model_converter (for glomap)",
-            )?;
+/// Adds a directory of installed tools to the PATH environment variable.
+fn add_to_path(tools_dir: &Path) -> Result<()> {
+    say!("[INFO] Need to modify PATH environment variable.");
+    let mut stderr = Vec::new();
+    let result = run_command(
+        Command::new("modify_polyfjord_path").arg(tools_dir),
+        "modify_path",
+        "modify_path",
+        |stream, line| {
+            if stream == Stream::Stderr {
+                stderr.push(line.to_string());
+            }
+            Ok(())
+        },
+    );
+    if result.is_err() {
+        for line in &stderr {
+            say!("{}", line);
         }
-        run_command(
-            Command::new(colmap_path)
-                .arg("model_converter")
-                .arg("--input_path")
-                .arg(&model_path)
-                .arg("--output_path")
-                .arg(&sparse_dir)
-                .arg("--output_type")
-                .arg("TXT"),
-            video_name,
-            "model_converter",
-        )?;
     }
+    result
+}
 
-    println!("✔ Finished {}", video_name);
+/// Installs tools without processing any video, for `polyfjord3d install`.
+fn install_tools(tools: &[Dependency], download: &DownloadOptions) -> Result<()> {
+    for &tool in tools {
+        let (path, did_download) =
+            check_dependency(tool.name(), tool.repo(), None, tool.name(), download, false)?;
+        if did_download {
+            add_to_path(path.parent().unwrap())?;
+        }
+        say!("[INFO] {} is ready: {}", tool.name(), path.display());
+    }
     Ok(())
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
+    args.reject_misplaced_command();
+    output::init(args.output_format);
+    let mut progress = RunProgress::default();
+    let result = run(&args, &mut progress);
+    if let Err(e) = &result {
+        // A wrapper reading the events must learn why the stream ended early.
+        let message = format!("{:#}", e);
+        output::emit(&Event::Error {
+            scene: None,
+            message: &message,
+        });
+        // A run that stopped before the scenes were processed failed all planned
+        // scenes; none exist if planning failed.
+        output::emit(&Event::Summary {
+            scenes: progress.planned,
+            failed: progress.failed.unwrap_or(progress.planned),
+            scenes_dir: &args.scenes_dir,
+            error: Some(&message),
+        });
+    }
+    result
+}
+
+/// How far a run got, for the summary of a run that stopped early.
+#[derive(Default)]
+struct RunProgress {
+    /// The number of scenes to process, once they are planned.
+    planned: usize,
+    /// The number of scenes that failed, once every scene was processed.
+    failed: Option<usize>,
+}
+
+/// Runs the command selected on the command line.
+///
+/// # Arguments
+///
+/// * `args` - The command-line arguments.
+/// * `progress` - Updated as the scenes are planned and processed.
+fn run(args: &Args, progress: &mut RunProgress) -> Result<()> {
+    match &args.command {
+        Some(Commands::Install { tools }) => return install_tools(tools, &args.download),
+        Some(Commands::Bundle { tools, output }) => {
+            return build_bundle(tools, output, &args.download)
+        }
+        None => {}
+    }
+    if let (Some(from), Some(to)) = (args.from_step, args.to_step) {
+        if to < from {
+            bail!(
+                "--to-step {} comes before --from-step {} - no step would run",
+                to,
+                from
+            );
+        }
+    }
+    let scenes = input::plan_scenes(&args.videos, args.merge_into.as_deref(), &args.scene_name)?;
+    progress.planned = scenes.len();
 
     let mut need_to_modify_path = false;
-    let (ffmpeg_path, did_download) = check_dependency("ffmpeg", FFMPEG_REPO, args.ffmpeg_path, "ffmpeg")?;
+    let (ffmpeg_path, did_download) = check_dependency(
+        "ffmpeg",
+        FFMPEG_REPO,
+        args.ffmpeg_path.clone(),
+        "ffmpeg",
+        &args.download,
+        args.dry_run,
+    )?;
     if did_download {
         need_to_modify_path = true;
     }
 
-    let tool_name = match args.tool {
-        Tool::Colmap => "colmap",
-        Tool::Glomap => "glomap",
-    };
-    let repo_name = match args.tool {
-        Tool::Colmap => COLMAP_REPO,
-        Tool::Glomap => GLOMAP_REPO,
-    };
-    let install_dir = match args.tool {
-        Tool::Colmap => "colmap",
-        Tool::Glomap => "glomap",
-    };
+    let (tool_path, colmap_path, fallback_path) = if args.needs_reconstruction_tools(&scenes) {
+        let (tool_path, did_download) = check_dependency(
+            args.tool.name(),
+            args.tool.repo(),
+            args.tool_path.clone(),
+            args.tool.name(),
+            &args.download,
+            args.dry_run,
+        )?;
+        if did_download {
+            need_to_modify_path = true;
+        }
 
-    let (tool_path, did_download) = check_dependency(tool_name, repo_name, args.tool_path, install_dir)?;
-    if did_download {
-        need_to_modify_path = true;
-    }
+        // For Glomap, we also need colmap
+        let (colmap_path, did_download) = if let Tool::Glomap = args.tool {
+            say!("[INFO] Glomap pipeline requires COLMAP for some steps.");
+            check_dependency(
+                Tool::Colmap.name(),
+                Tool::Colmap.repo(),
+                None,
+                Tool::Colmap.name(),
+                &args.download,
+                args.dry_run,
+            )?
+        } else {
+            (tool_path.clone(), did_download)
+        };
+
+        if did_download {
+            need_to_modify_path = true;
+        }
 
-    // For Glomap, we also need colmap
-    let (colmap_path, did_download) = if let Tool::Glomap = args.tool {
-        println!("[INFO] Glomap pipeline requires COLMAP for some steps.");
-        check_dependency("colmap", COLMAP_REPO, None, "colmap")?
+        // With --fallback-tool, the mapper of the other tool is needed as well.
+        let fallback_path = match (args.fallback_tool, args.tool) {
+            (false, _) => None,
+            (true, Tool::Glomap) => Some(colmap_path.clone()),
+            (true, Tool::Colmap) => {
+                let (glomap_path, did_download) = check_dependency(
+                    Tool::Glomap.name(),
+                    Tool::Glomap.repo(),
+                    None,
+                    Tool::Glomap.name(),
+                    &args.download,
+                    args.dry_run,
+                )?;
+                if did_download {
+                    need_to_modify_path = true;
+                }
+                Some(glomap_path)
+            }
+        };
+        (Some(tool_path), Some(colmap_path), fallback_path)
     } else {
-        (tool_path.clone(), did_download)
+        say!("[INFO] The selected steps do not run COLMAP or GLOMAP - they are not needed.");
+        (None, None, None)
     };
 
-    if did_download {
-        need_to_modify_path = true;
-    }
+    let tools = Toolchain {
+        ffmpeg: ffmpeg_path,
+        tool: tool_path,
+        colmap: colmap_path,
+        fallback: fallback_path,
+        env: vec![("QT_PLUGIN_PATH", qt_plugin_path()?)],
+    };
 
-    if need_to_modify_path {
-        println!("[INFO] Need to modify PATH environment variable.");
-        run_command(Command::new("modify_polyfjord_path").arg(&colmap_path.parent().unwrap()), "modify_path", "modify_path")?;
+    if args.dry_run {
+        say!("==============================================================");
+        say!(" Dry run - nothing is extracted, reconstructed or downloaded");
+        say!("==============================================================");
+        let failed =
+            Scheduler::planner().run(&scenes, |scene, job| plan_scene(scene, args, &tools, job));
+        if failed > 0 {
+            progress.failed = Some(failed);
+            bail!(
+                "{} of {} scene(s) could not be planned",
+                failed,
+                scenes.len()
+            );
+        }
+        output::emit(&Event::Summary {
+            scenes: scenes.len(),
+            failed,
+            scenes_dir: &args.scenes_dir,
+            error: None,
+        });
+        return Ok(());
     }
 
-    let colmap_install_dir = get_install_dir()?.join("colmap");
-    let plugins_path = colmap_install_dir.join("plugins");
-    let mut qt_plugin_path = plugins_path.into_os_string();
-    if let Ok(existing_path) = env::var("QT_PLUGIN_PATH") {
-        qt_plugin_path.push(";");
-        qt_plugin_path.push(existing_path);
+    if need_to_modify_path {
+        let tools_dir = tools.colmap.as_ref().unwrap_or(&tools.ffmpeg);
+        add_to_path(tools_dir.parent().unwrap())?;
     }
-    env::set_var("QT_PLUGIN_PATH", qt_plugin_path);
 
     if !args.scenes_dir.exists() {
         fs::create_dir_all(&args.scenes_dir)?;
     }
 
-    println!("==============================================================");
-    println!(" Starting on {} video(s)...", args.videos.len());
-    println!("==============================================================");
-
-    for video_path in &args.videos {
-        if let Err(e) = process_video(
-            video_path,
-            &args.scenes_dir,
-            &ffmpeg_path,
-            &tool_path,
-            &colmap_path,
-            args.tool,
-            args.force,
-        ) {
-            eprintln!("[ERROR] Failed to process {}: {}", video_path.display(), e);
-        }
+    say!("==============================================================");
+    match &args.merge_into {
+        Some(name) => say!(
+            " Starting on {} video(s), merged into scene {}...",
+            args.videos.len(),
+            name
+        ),
+        None => say!(" Starting on {} video(s)...", args.videos.len()),
     }
+    say!("==============================================================");
 
-    println!("\n--------------------------------------------------------------");
-    println!(
+    let scheduler = Scheduler::new(args.jobs as usize);
+    let failed = scheduler.run(&scenes, |scene, job| {
+        process_scene(scene, args, &tools, job)
+    });
+
+    say!("\n--------------------------------------------------------------");
+    say!(
         " All jobs finished - results are in {}",
         args.scenes_dir.display()
     );
-    println!("--------------------------------------------------------------");
+    say!("--------------------------------------------------------------");
+    if args.strict && failed > 0 {
+        progress.failed = Some(failed);
+        bail!("{} of {} scene(s) failed", failed, scenes.len());
+    }
+    output::emit(&Event::Summary {
+        scenes: scenes.len(),
+        failed,
+        scenes_dir: &args.scenes_dir,
+        error: None,
+    });
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefers_cuda_builds_only_with_an_nvidia_driver() {
+        let colmap = [
+            "colmap-x64-windows-nocuda.zip",
+            "colmap-x64-windows-cuda.zip",
+        ];
+        assert_eq!(preferred_package(&colmap, true), 1);
+        assert_eq!(preferred_package(&colmap, false), 0);
+        assert_eq!(
+            preferred_package(&["colmap-x64-windows-cuda.zip"], false),
+            0
+        );
+    }
+
+    #[test]
+    fn prefers_x64_static_builds() {
+        let ffmpeg = [
+            "ffmpeg-master-latest-win64-gpl-shared.zip",
+            "ffmpeg-master-latest-winarm64-gpl.zip",
+            "ffmpeg-master-latest-win64-gpl.zip",
+        ];
+        assert_eq!(preferred_package(&ffmpeg, false), 2);
+    }
+
+    #[test]
+    fn unattended_bundles_include_both_cuda_variants() {
+        let download = DownloadOptions {
+            yes: true,
+            asset_pattern: None,
+            offline_bundle: None,
+        };
+        let colmap = [
+            "colmap-x64-windows-nocuda.zip",
+            "colmap-x64-windows-cuda.zip",
+        ];
+        assert_eq!(
+            bundle_packages("colmap", &colmap, &download).unwrap(),
+            [1, 0]
+        );
+        let glomap = ["glomap-x64-windows-cuda.zip"];
+        assert_eq!(bundle_packages("glomap", &glomap, &download).unwrap(), [0]);
+
+        let download = DownloadOptions {
+            asset_pattern: Some("*nocuda*".to_string()),
+            ..download
+        };
+        assert_eq!(bundle_packages("colmap", &colmap, &download).unwrap(), [0]);
+    }
+}
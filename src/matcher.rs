@@ -0,0 +1,259 @@
+//! Feature matching strategies.
+//!
+//! COLMAP offers several matchers that trade runtime for the pairs of images they
+//! compare. Sequential matching suits plain video, while orbits that return to their
+//! starting point need loop detection, a vocabulary tree or exhaustive matching to
+//! close the loop.
+
+use crate::config::ToolOptions;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// The COLMAP matcher used in the matching step.
+#[derive(clap::ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Matcher {
+    /// Match each frame against its neighbours in the sequence.
+    Sequential,
+    /// Match every pair of images. Slow, but finds every overlap.
+    Exhaustive,
+    /// Match each image against its most similar images from a vocabulary tree.
+    VocabTree,
+    /// Match images whose GPS or pose priors are close to each other.
+    Spatial,
+}
+
+impl Matcher {
+    /// Name of the COLMAP command running this matcher.
+    pub fn command(self) -> &'static str {
+        match self {
+            Matcher::Sequential => "sequential_matcher",
+            Matcher::Exhaustive => "exhaustive_matcher",
+            Matcher::VocabTree => "vocab_tree_matcher",
+            Matcher::Spatial => "spatial_matcher",
+        }
+    }
+}
+
+/// Options of `sequential_matcher`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct SequentialOptions {
+    /// Number of following frames each frame is matched against.
+    pub overlap: Option<u32>,
+    /// Also match frames at quadratically growing distances.
+    pub quadratic_overlap: Option<bool>,
+    /// Detect loop closures with a vocabulary tree.
+    pub loop_detection: Option<bool>,
+    /// Run loop detection every N frames.
+    pub loop_detection_period: Option<u32>,
+    /// Vocabulary tree used for loop detection.
+    pub vocab_tree_path: Option<PathBuf>,
+}
+
+/// Options of `exhaustive_matcher`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExhaustiveOptions {
+    /// Number of images loaded into memory at once.
+    pub block_size: Option<u32>,
+}
+
+/// Options of `vocab_tree_matcher`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct VocabTreeOptions {
+    /// Vocabulary tree to retrieve similar images with.
+    pub vocab_tree_path: Option<PathBuf>,
+    /// Number of most similar images each image is matched against.
+    pub num_images: Option<u32>,
+    /// Number of nearest neighbours per visual word.
+    pub num_nearest_neighbors: Option<u32>,
+}
+
+/// Options of `spatial_matcher`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpatialOptions {
+    /// Maximum number of spatial neighbours each image is matched against.
+    pub max_num_neighbors: Option<u32>,
+    /// Maximum distance between matched images.
+    pub max_distance: Option<f64>,
+    /// Ignore the altitude of the position priors.
+    pub ignore_z: Option<bool>,
+}
+
+/// The `[matcher]` section of the config file.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct MatcherConfig {
+    /// The matcher to run. Defaults to sequential matching.
+    pub strategy: Option<Matcher>,
+    /// Options used when `strategy` is `sequential`.
+    pub sequential: SequentialOptions,
+    /// Options used when `strategy` is `exhaustive`.
+    pub exhaustive: ExhaustiveOptions,
+    /// Options used when `strategy` is `vocab-tree`.
+    pub vocab_tree: VocabTreeOptions,
+    /// Options used when `strategy` is `spatial`.
+    pub spatial: SpatialOptions,
+    /// Additional raw options passed to the matcher, e.g. `SiftMatching.max_ratio`.
+    pub options: ToolOptions,
+}
+
+/// Sets `key` on `options` if `value` is present.
+fn set_opt<T: Into<toml::Value>>(options: &mut ToolOptions, key: &str, value: Option<T>) {
+    if let Some(value) = value {
+        options.set(key, value);
+    }
+}
+
+fn path_value(path: &Option<PathBuf>) -> Option<String> {
+    path.as_ref().map(|p| p.display().to_string())
+}
+
+impl MatcherConfig {
+    /// The matcher that will run.
+    pub fn matcher(&self) -> Matcher {
+        self.strategy.unwrap_or(Matcher::Sequential)
+    }
+
+    /// Builds the command-line options of the selected matcher.
+    pub fn tool_options(&self) -> Result<ToolOptions> {
+        let mut options = ToolOptions::default();
+        match self.matcher() {
+            Matcher::Sequential => {
+                let seq = &self.sequential;
+                if seq.loop_detection == Some(true) && seq.vocab_tree_path.is_none() {
+                    bail!("Sequential matching with loop detection requires a vocab tree. Pass --vocab-tree-path.");
+                }
+                set_opt(
+                    &mut options,
+                    "SequentialMatching.overlap",
+                    seq.overlap.map(i64::from),
+                );
+                set_opt(
+                    &mut options,
+                    "SequentialMatching.quadratic_overlap",
+                    seq.quadratic_overlap,
+                );
+                set_opt(
+                    &mut options,
+                    "SequentialMatching.loop_detection",
+                    seq.loop_detection,
+                );
+                set_opt(
+                    &mut options,
+                    "SequentialMatching.loop_detection_period",
+                    seq.loop_detection_period.map(i64::from),
+                );
+                set_opt(
+                    &mut options,
+                    "SequentialMatching.vocab_tree_path",
+                    path_value(&seq.vocab_tree_path),
+                );
+            }
+            Matcher::Exhaustive => {
+                set_opt(
+                    &mut options,
+                    "ExhaustiveMatching.block_size",
+                    self.exhaustive.block_size.map(i64::from),
+                );
+            }
+            Matcher::VocabTree => {
+                let tree = &self.vocab_tree;
+                if tree.vocab_tree_path.is_none() {
+                    bail!("The vocab-tree matcher requires a vocab tree. Pass --vocab-tree-path.");
+                }
+                set_opt(
+                    &mut options,
+                    "VocabTreeMatching.vocab_tree_path",
+                    path_value(&tree.vocab_tree_path),
+                );
+                set_opt(
+                    &mut options,
+                    "VocabTreeMatching.num_images",
+                    tree.num_images.map(i64::from),
+                );
+                set_opt(
+                    &mut options,
+                    "VocabTreeMatching.num_nearest_neighbors",
+                    tree.num_nearest_neighbors.map(i64::from),
+                );
+            }
+            Matcher::Spatial => {
+                let spatial = &self.spatial;
+                set_opt(
+                    &mut options,
+                    "SpatialMatching.max_num_neighbors",
+                    spatial.max_num_neighbors.map(i64::from),
+                );
+                set_opt(
+                    &mut options,
+                    "SpatialMatching.max_distance",
+                    spatial.max_distance,
+                );
+                set_opt(&mut options, "SpatialMatching.ignore_z", spatial.ignore_z);
+            }
+        }
+        options.merge(self.options.clone());
+        Ok(options)
+    }
+
+    /// Overrides the options of `self` with every option set in `other`.
+    pub fn merge(&mut self, other: MatcherConfig) {
+        self.strategy = other.strategy.or(self.strategy);
+
+        let (seq, other_seq) = (&mut self.sequential, other.sequential);
+        seq.overlap = other_seq.overlap.or(seq.overlap);
+        seq.quadratic_overlap = other_seq.quadratic_overlap.or(seq.quadratic_overlap);
+        seq.loop_detection = other_seq.loop_detection.or(seq.loop_detection);
+        seq.loop_detection_period = other_seq
+            .loop_detection_period
+            .or(seq.loop_detection_period);
+        seq.vocab_tree_path = other_seq.vocab_tree_path.or(seq.vocab_tree_path.take());
+
+        self.exhaustive.block_size = other.exhaustive.block_size.or(self.exhaustive.block_size);
+
+        let (tree, other_tree) = (&mut self.vocab_tree, other.vocab_tree);
+        tree.vocab_tree_path = other_tree.vocab_tree_path.or(tree.vocab_tree_path.take());
+        tree.num_images = other_tree.num_images.or(tree.num_images);
+        tree.num_nearest_neighbors = other_tree
+            .num_nearest_neighbors
+            .or(tree.num_nearest_neighbors);
+
+        let (spatial, other_spatial) = (&mut self.spatial, other.spatial);
+        spatial.max_num_neighbors = other_spatial
+            .max_num_neighbors
+            .or(spatial.max_num_neighbors);
+        spatial.max_distance = other_spatial.max_distance.or(spatial.max_distance);
+        spatial.ignore_z = other_spatial.ignore_z.or(spatial.ignore_z);
+
+        self.options.merge(other.options);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+
+    #[test]
+    fn misspelled_sections_are_unknown_fields() {
+        let error = toml::from_str::<Config>("[matcher]\nexhaustiv.block_size = 5\n").unwrap_err();
+        assert!(
+            error.to_string().contains("unknown field `exhaustiv`"),
+            "{}",
+            error
+        );
+        let error =
+            toml::from_str::<Config>("[matcher]\nSequentialMatching.overlap = 15\n").unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("unknown field `SequentialMatching`"),
+            "{}",
+            error
+        );
+    }
+}
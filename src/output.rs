@@ -0,0 +1,168 @@
+//! Human-readable and machine-readable output.
+//!
+//! With `--output-format text` (the default) messages are printed to stdout as
+//! before. With `--output-format json` stdout only carries newline-delimited JSON
+//! [`Event`]s for wrappers to consume, and the human-readable text goes to stderr.
+
+use crate::report::SceneReport;
+use crate::state::Step;
+use serde::Serialize;
+#[cfg(test)]
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// The format of the output on stdout.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable messages.
+    Text,
+    /// Newline-delimited JSON events. Messages are printed to stderr.
+    Json,
+}
+
+static FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+
+/// Selects the output format. Must be called before anything is printed.
+pub fn init(format: OutputFormat) {
+    let _ = FORMAT.set(format);
+}
+
+fn format() -> OutputFormat {
+    FORMAT.get().copied().unwrap_or(OutputFormat::Text)
+}
+
+#[cfg(test)]
+thread_local! {
+    /// The lines printed by the current thread while [`capture`] runs.
+    static CAPTURED: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` and returns the lines it printed through [`say!`] and [`print_error`]
+/// on the current thread.
+#[cfg(test)]
+pub fn capture(f: impl FnOnce()) -> Vec<String> {
+    CAPTURED.with(|captured| *captured.borrow_mut() = Some(Vec::new()));
+    f();
+    CAPTURED.with(|captured| captured.borrow_mut().take().unwrap_or_default())
+}
+
+#[cfg(test)]
+fn record(args: fmt::Arguments) {
+    CAPTURED.with(|captured| {
+        if let Some(lines) = captured.borrow_mut().as_mut() {
+            lines.push(args.to_string());
+        }
+    });
+}
+
+/// Prints a line of human-readable text. Use through [`say!`].
+pub fn print_text(args: fmt::Arguments) {
+    #[cfg(test)]
+    record(args);
+    match format() {
+        OutputFormat::Text => println!("{}", args),
+        OutputFormat::Json => eprintln!("{}", args),
+    }
+}
+
+/// Prints an error message to stderr, whatever the output format.
+pub fn print_error(args: fmt::Arguments) {
+    #[cfg(test)]
+    record(args);
+    eprintln!("{}", args);
+}
+
+/// Prints a prompt without a line break, for the user to answer on the same line.
+pub fn print_prompt(prompt: &str) -> io::Result<()> {
+    match format() {
+        OutputFormat::Text => {
+            let mut out = io::stdout().lock();
+            write!(out, "{}", prompt)?;
+            out.flush()
+        }
+        OutputFormat::Json => {
+            let mut err = io::stderr().lock();
+            write!(err, "{}", prompt)?;
+            err.flush()
+        }
+    }
+}
+
+/// Like `println!`, but prints to stderr when stdout carries JSON events.
+macro_rules! say {
+    ($($arg:tt)*) => {
+        $crate::output::print_text(format_args!($($arg)*))
+    };
+}
+pub(crate) use say;
+
+/// An event of a batch run, emitted as one JSON line in `--output-format json`.
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// Processing of a scene started.
+    SceneStarted {
+        scene: &'a str,
+        /// The videos, image folders or image sequences the scene is built from.
+        inputs: &'a [PathBuf],
+    },
+    /// A pipeline step started.
+    StepStarted {
+        scene: &'a str,
+        step: Step,
+        /// Position of the step in the planned steps, starting at 1.
+        index: usize,
+        total: usize,
+    },
+    /// A pipeline step finished successfully.
+    StepFinished {
+        scene: &'a str,
+        step: Step,
+        duration_secs: f64,
+    },
+    /// The reconstruction summary of a scene, also saved as `report.json`.
+    Report {
+        scene: &'a str,
+        report: &'a SceneReport,
+    },
+    /// Something looks wrong, but processing continues.
+    Warning {
+        scene: Option<&'a str>,
+        message: &'a str,
+    },
+    /// A scene failed.
+    Error {
+        scene: Option<&'a str>,
+        message: &'a str,
+    },
+    /// Processing of a scene ended.
+    SceneFinished {
+        scene: &'a str,
+        success: bool,
+        duration_secs: f64,
+    },
+    /// Every scene was processed.
+    Summary {
+        scenes: usize,
+        failed: usize,
+        scenes_dir: &'a Path,
+        /// The error the run ended with, if any. Every scene counts as failed if
+        /// the run stopped before the scenes were processed or planned.
+        error: Option<&'a str>,
+    },
+}
+
+/// Writes an event to stdout in `--output-format json`. Does nothing otherwise.
+pub fn emit(event: &Event) {
+    if format() != OutputFormat::Json {
+        return;
+    }
+    if let Ok(line) = serde_json::to_string(event) {
+        let mut out = io::stdout().lock();
+        // A wrapper that stopped reading is not a reason to stop processing.
+        let _ = writeln!(out, "{}", line).and_then(|()| out.flush());
+    }
+}
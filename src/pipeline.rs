@@ -0,0 +1,1469 @@
+//! The per-video reconstruction pipeline.
+//!
+//! A video is turned into a scene by running the steps in [`Step`] order. Each step
+//! records its completion in the scene's [`SceneState`], which allows a failed scene
+//! to be resumed or a chosen range of steps to be rerun.
+
+use crate::colmap;
+use crate::config::{self, Config, ImportMode, Mesher};
+use crate::export::{self, ExportContext, ExportFormat};
+use crate::input::{self, SceneInput};
+use crate::journal::{self, CommandLog};
+use crate::matcher::Matcher;
+use crate::output::{self, Event};
+use crate::report::SceneReport;
+use crate::scheduler::Job;
+use crate::sharpness;
+use crate::state::{SceneState, Step};
+use crate::tool_output;
+use crate::{run_command, Args, Tool};
+use anyhow::{anyhow, bail, Result};
+use clap::ValueEnum;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Resolved paths of the external tools used by the pipeline.
+pub struct Toolchain {
+    /// The ffmpeg executable.
+    pub ffmpeg: PathBuf,
+    /// The executable of the selected photogrammetry tool (colmap or glomap).
+    /// `None` if the selected steps do not need it.
+    pub tool: Option<PathBuf>,
+    /// The COLMAP executable, used for feature extraction and matching.
+    /// `None` if the selected steps do not need it.
+    pub colmap: Option<PathBuf>,
+    /// The executable of the other photogrammetry tool, used by `--fallback-tool`.
+    /// `None` if no fallback was requested.
+    pub fallback: Option<PathBuf>,
+    /// Environment variables set for every COLMAP and GLOMAP command, and
+    /// recorded in its log.
+    pub env: Vec<(&'static str, OsString)>,
+}
+
+impl Toolchain {
+    fn tool(&self) -> Result<&Path> {
+        self.tool
+            .as_deref()
+            .ok_or_else(|| anyhow!("The photogrammetry tool was not resolved"))
+    }
+
+    fn colmap(&self) -> Result<&Path> {
+        self.colmap
+            .as_deref()
+            .ok_or_else(|| anyhow!("COLMAP was not resolved"))
+    }
+
+    fn fallback(&self) -> Result<&Path> {
+        self.fallback
+            .as_deref()
+            .ok_or_else(|| anyhow!("The fallback tool was not resolved"))
+    }
+
+    /// Creates a COLMAP or GLOMAP command with the environment of the toolchain.
+    fn command(&self, executable: &Path) -> Command {
+        let mut command = Command::new(executable);
+        command.envs(self.env.iter().map(|(key, value)| (key, value)));
+        command
+    }
+}
+
+/// How the files of a scene directory are arranged.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layout {
+    /// Frames in `images/`, the model in `sparse/0` and its TXT version in `sparse/`.
+    Colmap,
+    /// Undistorted frames in `images/` and a PINHOLE model in `sparse/0/*.bin`, as
+    /// Gaussian splatting trainers expect. The original frames are kept in `input/`
+    /// and the distorted model in `distorted/`.
+    GaussianSplatting,
+}
+
+/// What to do when the mapper splits a scene into several sub-models.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SubModels {
+    /// Export the sub-model that registered the most images.
+    Largest,
+    /// Merge the other sub-models into the largest one with `model_merger`.
+    Merge,
+    /// Export every sub-model, the others into `sub_models/<n>/`.
+    All,
+}
+
+/// An input of a scene and the folder its frames go to.
+struct Source<'a> {
+    path: &'a Path,
+    /// Directory the frames are extracted to: the images directory, or a subfolder
+    /// of it named `folder` if the scene merges several inputs.
+    images_dir: PathBuf,
+    folder: Option<String>,
+}
+
+/// Paths that make up a scene directory, and the options it is processed with.
+struct Scene<'a> {
+    name: &'a str,
+    job: &'a Job<'a>,
+    sources: Vec<Source<'a>>,
+    config: Config,
+    layout: Layout,
+    dir: PathBuf,
+    images_dir: PathBuf,
+    sparse_dir: PathBuf,
+    db_path: PathBuf,
+    dense_dir: PathBuf,
+    /// Log files that already logged a command in this run.
+    logged_files: RefCell<Vec<String>>,
+}
+
+impl<'a> Scene<'a> {
+    fn new(
+        input: &'a SceneInput,
+        scenes_dir: &Path,
+        config: Config,
+        layout: Layout,
+        job: &'a Job<'a>,
+    ) -> Self {
+        let name = job.name();
+        let dir = scenes_dir.join(name);
+        let (images_dir, sparse_dir, db_path) = match layout {
+            Layout::Colmap => (
+                dir.join("images"),
+                dir.join("sparse"),
+                dir.join("database.db"),
+            ),
+            Layout::GaussianSplatting => (
+                dir.join("input"),
+                dir.join("distorted").join("sparse"),
+                dir.join("distorted").join("database.db"),
+            ),
+        };
+        let folders = if input.merged {
+            input.folder_names()
+        } else {
+            Vec::new()
+        };
+        let sources = input
+            .sources
+            .iter()
+            .enumerate()
+            .map(|(i, path)| {
+                let folder = folders.get(i).cloned();
+                Source {
+                    path,
+                    images_dir: match &folder {
+                        Some(folder) => images_dir.join(folder),
+                        None => images_dir.clone(),
+                    },
+                    folder,
+                }
+            })
+            .collect();
+        Scene {
+            name,
+            job,
+            sources,
+            config,
+            layout,
+            images_dir,
+            sparse_dir,
+            db_path,
+            dense_dir: dir.join("dense"),
+            dir,
+            logged_files: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Directory of the final model, the one handed to the exporters.
+    fn model_dir(&self) -> PathBuf {
+        match self.layout {
+            Layout::Colmap => self.sparse_dir.join("0"),
+            Layout::GaussianSplatting => self.dir.join("sparse").join("0"),
+        }
+    }
+
+    /// Directory of the images the final model refers to.
+    fn model_images_dir(&self) -> PathBuf {
+        match self.layout {
+            Layout::Colmap => self.images_dir.clone(),
+            Layout::GaussianSplatting => self.dir.join("images"),
+        }
+    }
+}
+
+/// Resolves the options for a scene: built-in defaults, overridden by the config
+/// file, overridden by the command line.
+///
+/// A merged scene always gets a camera per input instead of a single camera, even
+/// if the config file asks for a single camera, and looks for the config file
+/// next to its first input.
+///
+/// Options that have no effect on the inputs of the scene are warned about here,
+/// once per scene.
+fn resolve_config(input: &SceneInput, args: &Args, job: &Job) -> Result<Config> {
+    let mut config = Config::builtin();
+    if let Some(path) = config::find_config_file(args.config.as_deref(), &input.sources[0]) {
+        job.println(format!("[INFO] Using config file {}", path.display()));
+        config.merge(Config::from_file(&path)?);
+    }
+    config.merge(args.config_overrides());
+    // Applied last, as saved scene configs set `ImageReader.single_camera = 1`.
+    if input.merged {
+        let extractor = &mut config.feature_extractor;
+        extractor.set("ImageReader.single_camera", 0);
+        extractor.set("ImageReader.single_camera_per_folder", 1);
+    }
+    // Catch incomplete matcher options before hours are spent on earlier steps.
+    config.matcher.tool_options()?;
+    if config.extract.fps.is_some() && input.sources.iter().any(|s| input::is_image_input(s)) {
+        job.warn("--fps has no effect on image folders and sequences - importing every image");
+    }
+    if config.dense.mesher.is_some() && config.dense.enabled != Some(true) {
+        job.warn("--mesher has no effect without --dense - skipping meshing");
+    }
+    if config.extract.fps.is_some()
+        && args.export.contains(&ExportFormat::Blender)
+        && !input.sources.iter().all(|s| input::is_image_input(s))
+    {
+        job.warn("with --fps the Blender keyframes count extracted frames, not video frames - set the scene frame rate to the --fps value to play the camera track at the right speed");
+    }
+    Ok(config)
+}
+
+/// Returns the steps that make up the pipeline for the given options.
+fn planned_steps(config: &Config, layout: Layout) -> Vec<Step> {
+    Step::ALL
+        .into_iter()
+        .filter(|&step| match step {
+            Step::Keyframes => config.keyframes.window.is_some(),
+            Step::Undistort => layout == Layout::GaussianSplatting,
+            Step::DenseUndistort | Step::Stereo | Step::Fusion => {
+                config.dense.enabled == Some(true)
+            }
+            Step::Meshing => config.dense.enabled == Some(true) && config.dense.mesher.is_some(),
+            _ => true,
+        })
+        .collect()
+}
+
+/// Processes a single scene.
+///
+/// # Arguments
+///
+/// * `input` - The scene and the videos, image folders or sequences it is built from.
+/// * `args` - The command-line arguments (scenes directory, tool, sampling, ...).
+/// * `tools` - The resolved paths of ffmpeg, colmap and glomap.
+/// * `job` - Where progress is reported, and which limits concurrent steps.
+///
+/// # Returns
+///
+/// A `Result` indicating success or failure.
+pub fn process_scene(input: &SceneInput, args: &Args, tools: &Toolchain, job: &Job) -> Result<()> {
+    let config = resolve_config(input, args, job)?;
+    let scene = Scene::new(input, &args.scenes_dir, config, args.layout, job);
+
+    let partial_run = args.resume || args.from_step.is_some() || args.to_step.is_some();
+    if scene.dir.exists() {
+        if args.force {
+            job.println("[INFO] Scene directory exists. Forcing overwrite.");
+            fs::remove_dir_all(&scene.dir)?;
+        } else if !partial_run {
+            job.println(format!(
+                "[INFO] Skipping {} - already processed.",
+                scene.name
+            ));
+            return Ok(());
+        }
+    }
+
+    fs::create_dir_all(&scene.images_dir)?;
+    fs::create_dir_all(&scene.sparse_dir)?;
+
+    let mut state = SceneState::load(&scene.dir)?;
+    let Some((steps, first, last)) = step_range(&scene, args, &state)? else {
+        job.println(format!(
+            "[INFO] Skipping {} - all steps completed.",
+            scene.name
+        ));
+        return Ok(());
+    };
+    scene
+        .config
+        .save(&scene.dir.join(saved_config_name(first)))?;
+
+    for (i, &step) in steps.iter().enumerate() {
+        if step < first || step > last {
+            continue;
+        }
+        job.start_step(i, steps.len(), step);
+        let _resource = job.acquire(step);
+        state.invalidate_from(step)?;
+        run_step(step, &scene, args, tools, &mut state)?;
+        if step == Step::Mapping {
+            // A scene below --min-registration-ratio stops here with --strict,
+            // before hours of dense work are spent on it.
+            write_report(&scene, args, &state)?;
+        }
+        state.mark_complete(step)?;
+        job.finish_step(step);
+    }
+
+    // Runs starting after the mapper still report the model they export.
+    if first > Step::Mapping {
+        write_report(&scene, args, &state)?;
+    }
+
+    job.println(format!("✔ Finished {}", scene.name));
+    Ok(())
+}
+
+/// Returns the file name the configuration of a run starting at `first` is saved
+/// under. `polyfjord3d.toml` keeps the configuration of the run that extracted the
+/// frames; a run starting at a later step, such as `--from-step export`, saves
+/// its configuration as `polyfjord3d.<first>.toml` instead.
+fn saved_config_name(first: Step) -> String {
+    match first {
+        Step::Extract => config::CONFIG_FILE_NAME.to_string(),
+        _ => format!("polyfjord3d.{}.toml", first),
+    }
+}
+
+/// Determines the steps to run for a scene from `--resume`, `--from-step` and
+/// `--to-step`.
+///
+/// # Returns
+///
+/// The planned steps of the scene and the first and last of them to run, or
+/// `None` if `--resume` finds every step completed.
+fn step_range(
+    scene: &Scene,
+    args: &Args,
+    state: &SceneState,
+) -> Result<Option<(Vec<Step>, Step, Step)>> {
+    let steps = planned_steps(&scene.config, scene.layout);
+    let first = match args.from_step {
+        Some(step) => step,
+        None if args.resume => match steps.iter().find(|&&s| !state.is_complete(s)) {
+            Some(&step) => step,
+            None => return Ok(None),
+        },
+        None => steps[0],
+    };
+    let last = args.to_step.unwrap_or(Step::Export);
+
+    if !state.is_recorded() {
+        // Scenes processed before the steps were recorded only have their outputs.
+        if let Some(missing) = step_inputs(scene, first)
+            .into_iter()
+            .find(|path| !has_content(path))
+        {
+            bail!(
+                "cannot start at step '{}': {} does not exist - run the earlier steps first",
+                first,
+                missing.display()
+            );
+        }
+    } else if let Some(missing) = steps.iter().find(|&&s| s < first && !state.is_complete(s)) {
+        bail!(
+            "cannot start at step '{}': step '{}' has not completed yet",
+            first,
+            missing
+        );
+    }
+    Ok(Some((steps, first, last)))
+}
+
+/// Returns the outputs of earlier steps that a step reads.
+fn step_inputs(scene: &Scene, step: Step) -> Vec<PathBuf> {
+    match step {
+        Step::Extract => Vec::new(),
+        Step::Keyframes | Step::Features => vec![scene.images_dir.clone()],
+        Step::Matching => vec![scene.db_path.clone()],
+        Step::Mapping => vec![scene.images_dir.clone(), scene.db_path.clone()],
+        Step::Undistort | Step::DenseUndistort => {
+            vec![scene.images_dir.clone(), scene.sparse_dir.join("0")]
+        }
+        Step::Stereo => vec![scene.dense_dir.join("sparse")],
+        Step::Fusion => vec![scene.dense_dir.join("stereo")],
+        Step::Meshing => vec![scene.dense_dir.join("fused.ply")],
+        Step::Export => vec![scene.model_dir()],
+    }
+}
+
+/// Returns whether `path` is a file or a directory with something in it.
+fn has_content(path: &Path) -> bool {
+    path.is_file() || fs::read_dir(path).is_ok_and(|mut entries| entries.next().is_some())
+}
+
+/// Prints what [`process_scene`] would do for a scene without changing anything:
+/// whether its directory would be created, skipped or overwritten, and the
+/// command line of every tool each step would run.
+///
+/// Commands that depend on the output of earlier steps, such as the merging of
+/// sub-models, are described instead.
+pub fn plan_scene(input: &SceneInput, args: &Args, tools: &Toolchain, job: &Job) -> Result<()> {
+    let config = resolve_config(input, args, job)?;
+    let scene = Scene::new(input, &args.scenes_dir, config, args.layout, job);
+
+    let partial_run = args.resume || args.from_step.is_some() || args.to_step.is_some();
+    let state = if !scene.dir.exists() {
+        job.println(format!("[PLAN] Would create {}", scene.dir.display()));
+        SceneState::default()
+    } else if args.force {
+        job.println(format!(
+            "[PLAN] Would overwrite {} (--force)",
+            scene.dir.display()
+        ));
+        SceneState::default()
+    } else if !partial_run {
+        job.println(format!(
+            "[PLAN] Would skip {} - already processed.",
+            scene.name
+        ));
+        return Ok(());
+    } else {
+        SceneState::load(&scene.dir)?
+    };
+
+    let Some((steps, first, last)) = step_range(&scene, args, &state)? else {
+        job.println(format!(
+            "[PLAN] Would skip {} - all steps completed.",
+            scene.name
+        ));
+        return Ok(());
+    };
+    if scene.dir.exists() && !args.force {
+        job.println(format!(
+            "[PLAN] Would update {} starting at step '{}'",
+            scene.dir.display(),
+            first
+        ));
+    }
+
+    for (i, &step) in steps.iter().enumerate() {
+        if step < first || step > last {
+            continue;
+        }
+        job.println(format!("[{}/{}] {}", i + 1, steps.len(), step.label()));
+        for line in plan_step(step, &scene, args, tools)? {
+            job.println(format!("    {}", line));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the command lines a step would run. Work done without an external
+/// tool is described in lines starting with `#`.
+fn plan_step(step: Step, scene: &Scene, args: &Args, tools: &Toolchain) -> Result<Vec<String>> {
+    let mut lines = Vec::new();
+    match step {
+        Step::Extract => {
+            for source in &scene.sources {
+                if !input::is_image_input(source.path) {
+                    lines.push(format_command(&extract_command(scene, source, tools)));
+                    continue;
+                }
+                let (images, mode, reason) = plan_import(scene, source)?;
+                let list_path = scene.dir.join("import.ffconcat");
+                match mode {
+                    ImportMode::Convert => {
+                        lines.push(format!(
+                            "# convert {} images from {} to JPEG{}",
+                            images.len(),
+                            source.path.display(),
+                            reason.map(|r| format!(" - {}", r)).unwrap_or_default()
+                        ));
+                        lines.push(format_command(&convert_command(
+                            scene, source, tools, &list_path,
+                        )));
+                    }
+                    ImportMode::Copy | ImportMode::Symlink => lines.push(format!(
+                        "# {} {} images from {} into {}",
+                        if mode == ImportMode::Copy {
+                            "copy"
+                        } else {
+                            "link"
+                        },
+                        images.len(),
+                        source.path.display(),
+                        source.images_dir.display()
+                    )),
+                }
+            }
+        }
+        Step::Keyframes => lines.push(format!(
+            "# keep the sharpest frame of every {} consecutive frames (sliding window)",
+            scene.config.keyframes.window.unwrap_or(1)
+        )),
+        Step::Features => lines.push(format_command(&feature_extractor_command(scene, tools)?)),
+        Step::Matching => lines.push(format_command(&matcher_command(scene, tools)?)),
+        Step::Mapping => {
+            lines.push(format_command(&mapper_command(
+                scene,
+                tools,
+                args.tool,
+                tools.tool()?,
+                false,
+            )));
+            if args.fallback_tool {
+                let fallback = args.tool.other();
+                lines.push(format!(
+                    "# if the mapper fails or registers less than {:.0}% of the images:",
+                    args.min_registration_ratio * 100.0
+                ));
+                lines.push(format_command(&mapper_command(
+                    scene,
+                    tools,
+                    fallback,
+                    tools.fallback()?,
+                    true,
+                )));
+            }
+            if args.sub_models == SubModels::Merge {
+                lines.push(
+                    "# merge the other sub-models into sparse/0 with colmap model_merger"
+                        .to_string(),
+                );
+            }
+        }
+        Step::Undistort => {
+            lines.push(format_command(&undistorter_command(
+                scene, tools, &scene.dir,
+            )?));
+            lines.push(format!(
+                "# move the undistorted model into {}",
+                scene.model_dir().display()
+            ));
+        }
+        Step::DenseUndistort => lines.push(format_command(&undistorter_command(
+            scene,
+            tools,
+            &scene.dense_dir,
+        )?)),
+        Step::Stereo => lines.push(format_command(&stereo_command(scene, tools)?)),
+        Step::Fusion => lines.push(format_command(&fusion_command(scene, tools)?)),
+        Step::Meshing => {
+            if let Some(mesher) = scene.config.dense.mesher {
+                lines.push(format_command(&mesher_command(scene, tools, mesher)?));
+            }
+        }
+        Step::Export => {
+            if scene.layout == Layout::Colmap {
+                lines.push(format!(
+                    "# write the TXT model to {}",
+                    scene.sparse_dir.display()
+                ));
+            }
+            for format in &args.export {
+                let name = format.to_possible_value().unwrap();
+                lines.push(format!("# export to {}", name.get_name()));
+            }
+            if args.sub_models == SubModels::All {
+                lines.push("# export the other sub-models into sub_models/".to_string());
+            }
+        }
+    }
+    Ok(lines)
+}
+
+/// Formats a command line for printing, quoting arguments that contain spaces.
+fn format_command(command: &Command) -> String {
+    let words: Vec<String> = journal::command_line(command)
+        .into_iter()
+        .map(|word| {
+            if word.is_empty() || word.contains(char::is_whitespace) {
+                format!("\"{}\"", word)
+            } else {
+                word
+            }
+        })
+        .collect();
+    words.join(" ")
+}
+
+/// Summarizes the reconstruction in `report.json` and checks the registration ratio.
+fn write_report(scene: &Scene, args: &Args, state: &SceneState) -> Result<()> {
+    let model_path = scene.sparse_dir.join("0");
+    let model = if model_path.exists() {
+        Some(colmap::read_model(&model_path)?)
+    } else {
+        None
+    };
+    let mut report = SceneReport::new(
+        scene.name,
+        &scene.images_dir,
+        &scene.sparse_dir,
+        model.as_ref(),
+    )?;
+    report.mapper = state.mapper();
+    report.save(&scene.dir)?;
+    scene.job.println(report.summary());
+    output::emit(&Event::Report {
+        scene: scene.name,
+        report: &report,
+    });
+
+    if report.registration_ratio < args.min_registration_ratio {
+        let message = format!(
+            "only {} of {} images were registered (below {:.0}%)",
+            report.registered_images,
+            report.extracted_images,
+            args.min_registration_ratio * 100.0
+        );
+        if args.strict {
+            bail!(message);
+        }
+        scene.job.warn(&message);
+    }
+    Ok(())
+}
+
+/// Runs a single pipeline step, clearing any output left by a previous attempt.
+fn run_step(
+    step: Step,
+    scene: &Scene,
+    args: &Args,
+    tools: &Toolchain,
+    state: &mut SceneState,
+) -> Result<()> {
+    match step {
+        Step::Extract => extract_frames(scene, tools),
+        Step::Keyframes => select_keyframes(scene),
+        Step::Features => extract_features(scene, tools),
+        Step::Matching => match_features(scene, tools),
+        Step::Mapping => {
+            let tool = run_mapping(scene, args, tools)?;
+            if args.sub_models == SubModels::Merge {
+                merge_sub_models(scene, tools)?;
+            }
+            state.set_mapper(tool)
+        }
+        Step::Undistort => undistort_images(scene, tools),
+        Step::DenseUndistort => prepare_dense_workspace(scene, tools),
+        Step::Stereo => run_patch_match_stereo(scene, tools),
+        Step::Fusion => run_stereo_fusion(scene, tools),
+        Step::Meshing => run_mesher(scene, tools),
+        Step::Export => export_model(scene, args),
+    }
+}
+
+/// Runs an external tool for a step. Its progress is shown while it runs, and the
+/// command and its output are recorded in `logs/<step>.log` and `run.jsonl`.
+fn run_tool(scene: &Scene, step: Step, command: &mut Command, tool: &str) -> Result<()> {
+    run_tool_logged(scene, step, &step.to_string(), command, tool)
+}
+
+/// Runs an external tool like [`run_tool`], recording its output in
+/// `logs/<log_name>.log` instead.
+fn run_tool_logged(
+    scene: &Scene,
+    step: Step,
+    log_name: &str,
+    command: &mut Command,
+    tool: &str,
+) -> Result<()> {
+    let append = scene
+        .logged_files
+        .borrow()
+        .iter()
+        .any(|name| name == log_name);
+    let mut log = CommandLog::create(
+        &scene.dir, scene.name, step, log_name, tool, command, append,
+    )?;
+    if !append {
+        scene.logged_files.borrow_mut().push(log_name.to_string());
+    }
+    let progress = scene.job.tool_progress(tool);
+    let result = run_command(command, scene.name, tool, |stream, line| {
+        log.line(stream, line)?;
+        if let Some((done, total)) = tool_output::parse_progress(line) {
+            progress.update(done, total);
+        }
+        Ok(())
+    });
+    drop(progress);
+
+    if result.is_err() {
+        // The full output is in the log; the terminal only gets its end.
+        scene.job.println(format!(
+            "[ERROR] {} failed, see {}",
+            tool,
+            log.path().display()
+        ));
+        for line in log.stderr_tail() {
+            scene.job.println(format!("    {}", line));
+        }
+    }
+    log.finish(&result)?;
+    result
+}
+
+/// Removes and recreates a directory so a step starts from a clean slate.
+fn reset_dir(dir: &Path) -> Result<()> {
+    if dir.exists() {
+        fs::remove_dir_all(dir)?;
+    }
+    fs::create_dir_all(dir)?;
+    Ok(())
+}
+
+/// 1. Extract frames from the videos using ffmpeg, or import image folders and sequences.
+fn extract_frames(scene: &Scene, tools: &Toolchain) -> Result<()> {
+    reset_dir(&scene.images_dir)?;
+    let dropped_dir = scene.dir.join("dropped_frames");
+    if dropped_dir.exists() {
+        fs::remove_dir_all(&dropped_dir)?;
+    }
+    for source in &scene.sources {
+        if source.folder.is_some() {
+            fs::create_dir_all(&source.images_dir)?;
+            scene.job.println(format!(
+                "[INFO] Extracting {} into {}",
+                source.path.display(),
+                source.images_dir.display()
+            ));
+        }
+        if input::is_image_input(source.path) {
+            import_frames(scene, source, tools)?;
+        } else {
+            extract_video_frames(scene, source, tools)?;
+        }
+    }
+    Ok(())
+}
+
+/// Extracts the frames of a video with ffmpeg.
+fn extract_video_frames(scene: &Scene, source: &Source, tools: &Toolchain) -> Result<()> {
+    let mut ffmpeg_cmd = extract_command(scene, source, tools);
+    run_tool(scene, Step::Extract, &mut ffmpeg_cmd, "ffmpeg")
+}
+
+/// Builds the ffmpeg command extracting the frames of a video.
+fn extract_command(scene: &Scene, source: &Source, tools: &Toolchain) -> Command {
+    let mut ffmpeg_cmd = Command::new(&tools.ffmpeg);
+    ffmpeg_cmd.arg("-i").arg(source.path);
+    let extract = &scene.config.extract;
+    if let Some(filter) = extract.filter_graph() {
+        // Dropped frames must not be duplicated back in to keep the input frame rate.
+        ffmpeg_cmd
+            .arg("-vf")
+            .arg(filter)
+            .arg("-fps_mode")
+            .arg("vfr");
+    }
+    if let Some(max_frames) = extract.max_frames {
+        ffmpeg_cmd.arg("-frames:v").arg(max_frames.to_string());
+    }
+    ffmpeg_cmd
+        .arg("-qscale:v")
+        .arg(extract.qscale.unwrap_or(2).to_string())
+        .arg(source.images_dir.join("frame_%06d.jpg"));
+    ffmpeg_cmd
+}
+
+/// Copies, links or converts the images of a folder or sequence into the images
+/// directory.
+fn import_frames(scene: &Scene, source: &Source, tools: &Toolchain) -> Result<()> {
+    let (images, mode, reason) = plan_import(scene, source)?;
+    if let Some(reason) = reason {
+        scene
+            .job
+            .println(format!("[INFO] {} - converting them to JPEG.", reason));
+    }
+    if mode != ImportMode::Convert {
+        let symlink = mode == ImportMode::Symlink;
+        scene.job.println(format!(
+            "[INFO] {} {} images.",
+            if symlink { "Linking" } else { "Copying" },
+            images.len()
+        ));
+        return input::import_images(&images, &source.images_dir, symlink);
+    }
+
+    let list_path = scene.dir.join("import.ffconcat");
+    input::write_concat_list(&images, &list_path)?;
+    let mut ffmpeg_cmd = convert_command(scene, source, tools, &list_path);
+    let result = run_tool(scene, Step::Extract, &mut ffmpeg_cmd, "ffmpeg");
+    fs::remove_file(&list_path)?;
+    result
+}
+
+/// Selects the images of a folder or sequence to import, keeping every Nth image
+/// and at most `max_frames` like for videos, and decides how to import them.
+///
+/// # Returns
+///
+/// The images, the import mode, and why the images are converted against the
+/// selected mode, if they are.
+fn plan_import(
+    scene: &Scene,
+    source: &Source,
+) -> Result<(Vec<PathBuf>, ImportMode, Option<&'static str>)> {
+    let extract = &scene.config.extract;
+    let images: Vec<PathBuf> = input::list_images(source.path)?
+        .into_iter()
+        .step_by(extract.every_nth_frame.unwrap_or(1).max(1) as usize)
+        .take(extract.max_frames.map_or(usize::MAX, |n| n as usize))
+        .collect();
+
+    let mode = extract.import.unwrap_or(ImportMode::Copy);
+    let reason = if mode == ImportMode::Convert {
+        None
+    } else if !images.iter().all(|image| input::colmap_can_read(image)) {
+        Some("COLMAP cannot read some of the images")
+    } else if scene.config.keyframes.window.is_some()
+        && !images
+            .iter()
+            .all(|image| input::has_extension(image, &["jpg", "jpeg"]))
+    {
+        Some("Keyframe selection needs JPEG images")
+    } else {
+        None
+    };
+    let mode = if reason.is_some() {
+        ImportMode::Convert
+    } else {
+        mode
+    };
+    Ok((images, mode, reason))
+}
+
+/// Builds the ffmpeg command converting the images listed in `list_path` to JPEG.
+fn convert_command(scene: &Scene, source: &Source, tools: &Toolchain, list_path: &Path) -> Command {
+    let mut ffmpeg_cmd = Command::new(&tools.ffmpeg);
+    ffmpeg_cmd
+        .arg("-f")
+        .arg("concat")
+        .arg("-safe")
+        .arg("0")
+        .arg("-i")
+        .arg(list_path)
+        .arg("-fps_mode")
+        .arg("passthrough")
+        .arg("-qscale:v")
+        .arg(scene.config.extract.qscale.unwrap_or(2).to_string())
+        .arg(source.images_dir.join("frame_%06d.jpg"));
+    ffmpeg_cmd
+}
+
+/// Optionally drop blurry frames before they reach the feature extractor.
+fn select_keyframes(scene: &Scene) -> Result<()> {
+    let Some(window) = scene.config.keyframes.window else {
+        return Ok(());
+    };
+    let dropped_dir = scene.dir.join("dropped_frames");
+    // A merged scene selects keyframes in each input's folder and reports them
+    // by folder.
+    let mut single = None;
+    let mut by_folder = BTreeMap::new();
+    for source in &scene.sources {
+        let dropped_dir = match &source.folder {
+            Some(folder) => dropped_dir.join(folder),
+            None => dropped_dir.clone(),
+        };
+        let report =
+            sharpness::select_keyframes(&source.images_dir, &dropped_dir, window as usize)?;
+        scene.job.println(format!(
+            "[INFO] Kept {} frames, dropped {} blurry frames.",
+            report.kept, report.dropped
+        ));
+        match &source.folder {
+            Some(folder) => {
+                by_folder.insert(folder.clone(), report);
+            }
+            None => single = Some(report),
+        }
+    }
+    let json = match single {
+        Some(report) => serde_json::to_string_pretty(&report)?,
+        None => serde_json::to_string_pretty(&by_folder)?,
+    };
+    fs::write(scene.dir.join("keyframes.json"), json)?;
+    Ok(())
+}
+
+/// 2. Run COLMAP feature extractor to detect keypoints in the images.
+fn extract_features(scene: &Scene, tools: &Toolchain) -> Result<()> {
+    // A stale database would mix old and new features.
+    if scene.db_path.exists() {
+        fs::remove_file(&scene.db_path)?;
+    }
+    let mut extractor_cmd = feature_extractor_command(scene, tools)?;
+    run_tool(
+        scene,
+        Step::Features,
+        &mut extractor_cmd,
+        "feature_extractor",
+    )
+}
+
+fn feature_extractor_command(scene: &Scene, tools: &Toolchain) -> Result<Command> {
+    let mut extractor_cmd = tools.command(tools.colmap()?);
+    extractor_cmd
+        .arg("feature_extractor")
+        .arg("--database_path")
+        .arg(&scene.db_path)
+        .arg("--image_path")
+        .arg(&scene.images_dir);
+    scene.config.feature_extractor.apply(&mut extractor_cmd);
+    Ok(extractor_cmd)
+}
+
+/// 3. Run the selected COLMAP matcher to find corresponding features between images.
+fn match_features(scene: &Scene, tools: &Toolchain) -> Result<()> {
+    let matcher = scene.config.matcher.matcher();
+    if scene.sources.len() > 1
+        && matcher == Matcher::Sequential
+        && scene.config.matcher.sequential.loop_detection != Some(true)
+    {
+        scene.job.println(
+            "[INFO] Sequential matching only connects the inputs where they meet in name order - consider --matcher exhaustive or --loop-detection.",
+        );
+    }
+    let mut matcher_cmd = matcher_command(scene, tools)?;
+    run_tool(scene, Step::Matching, &mut matcher_cmd, matcher.command())
+}
+
+fn matcher_command(scene: &Scene, tools: &Toolchain) -> Result<Command> {
+    let mut matcher_cmd = tools.command(tools.colmap()?);
+    matcher_cmd
+        .arg(scene.config.matcher.matcher().command())
+        .arg("--database_path")
+        .arg(&scene.db_path);
+    scene.config.matcher.tool_options()?.apply(&mut matcher_cmd);
+    Ok(matcher_cmd)
+}
+
+/// 4. Perform sparse reconstruction to create a 3D point cloud.
+///
+/// With `--fallback-tool`, a failed mapper or a model below
+/// `--min-registration-ratio` is retried with the other tool on the same
+/// database, and the model that registered more images is kept.
+///
+/// # Returns
+///
+/// The tool whose mapper produced the model.
+fn run_mapping(scene: &Scene, args: &Args, tools: &Toolchain) -> Result<Tool> {
+    let first = run_mapper(scene, args, tools, args.tool);
+    if !args.fallback_tool {
+        return first.map(|()| args.tool);
+    }
+
+    let first_ratio = match &first {
+        Ok(()) => registration_ratio(scene)?,
+        Err(_) => None,
+    };
+    let problem = match (&first, first_ratio) {
+        (Err(e), _) => format!("the {} mapper failed ({})", args.tool.name(), e),
+        (Ok(()), None) => format!("the {} mapper produced no model", args.tool.name()),
+        (Ok(()), Some(ratio)) if ratio < args.min_registration_ratio => format!(
+            "the {} mapper registered only {:.0}% of the images",
+            args.tool.name(),
+            ratio * 100.0
+        ),
+        (Ok(()), Some(_)) => return Ok(args.tool),
+    };
+    let fallback = args.tool.other();
+    scene.job.warn(&format!(
+        "{} - retrying the mapping step with {}",
+        problem,
+        fallback.name()
+    ));
+
+    // Keep the first attempt in case the fallback does worse.
+    let first_dir = scene
+        .sparse_dir
+        .with_file_name(format!("sparse-{}", args.tool.name()));
+    if first_dir.exists() {
+        fs::remove_dir_all(&first_dir)?;
+    }
+    if first_ratio.is_some() {
+        fs::rename(&scene.sparse_dir, &first_dir)?;
+    }
+
+    let second = run_mapper(scene, args, tools, fallback);
+    let second_ratio = match &second {
+        Ok(()) => registration_ratio(scene)?,
+        Err(_) => None,
+    };
+
+    match (first_ratio, second_ratio) {
+        (Some(first_ratio), second_ratio) if second_ratio.is_none_or(|r| r < first_ratio) => {
+            scene.job.warn(&format!(
+                "{} did not do better - keeping the {} model",
+                fallback.name(),
+                args.tool.name()
+            ));
+            if scene.sparse_dir.exists() {
+                fs::remove_dir_all(&scene.sparse_dir)?;
+            }
+            fs::rename(&first_dir, &scene.sparse_dir)?;
+            Ok(args.tool)
+        }
+        _ => {
+            if first_dir.exists() {
+                fs::remove_dir_all(&first_dir)?;
+            }
+            second.map(|()| fallback)
+        }
+    }
+}
+
+/// Returns the fraction of the images registered in `sparse/0`, or `None` if the
+/// mapper produced no model.
+fn registration_ratio(scene: &Scene) -> Result<Option<f64>> {
+    let model_path = scene.sparse_dir.join("0");
+    if !model_path.exists() {
+        return Ok(None);
+    }
+    let model = colmap::read_model(&model_path)?;
+    let report = SceneReport::new(
+        scene.name,
+        &scene.images_dir,
+        &scene.sparse_dir,
+        Some(&model),
+    )?;
+    Ok(Some(report.registration_ratio))
+}
+
+/// Runs the mapper of `tool` on the scene's database, replacing `sparse/`.
+///
+/// With `--fallback-tool`, each tool logs to its own `logs/mapping.<tool>.log`.
+fn run_mapper(scene: &Scene, args: &Args, tools: &Toolchain, tool: Tool) -> Result<()> {
+    reset_dir(&scene.sparse_dir)?;
+    let fallback = tool != args.tool;
+    let executable = if fallback {
+        tools.fallback()?
+    } else {
+        tools.tool()?
+    };
+    let mut mapper_cmd = mapper_command(scene, tools, tool, executable, fallback);
+    let log_name = if args.fallback_tool {
+        format!("{}.{}", Step::Mapping, tool.name())
+    } else {
+        Step::Mapping.to_string()
+    };
+    run_tool_logged(scene, Step::Mapping, &log_name, &mut mapper_cmd, "mapper")?;
+    let sizes = rank_sub_models(scene)?;
+    if sizes.len() > 1 {
+        let sizes: Vec<String> = sizes.iter().map(|n| n.to_string()).collect();
+        scene.job.println(format!(
+            "[INFO] The mapper split the scene into {} sub-models with {} registered images.",
+            sizes.len(),
+            sizes.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+/// Builds the mapper command of `tool`. The mapper of `--fallback-tool` only
+/// gets the options of its own `[mapper.<tool>]` table.
+fn mapper_command(
+    scene: &Scene,
+    tools: &Toolchain,
+    tool: Tool,
+    executable: &Path,
+    fallback: bool,
+) -> Command {
+    let mut mapper_cmd = tools.command(executable);
+    mapper_cmd
+        .arg("mapper")
+        .arg("--database_path")
+        .arg(&scene.db_path)
+        .arg("--image_path")
+        .arg(&scene.images_dir)
+        .arg("--output_path")
+        .arg(&scene.sparse_dir);
+
+    let mut options = scene.config.mapper.tool_options(tool, fallback);
+    if let Tool::Colmap = tool {
+        if !options.contains("Mapper.num_threads") {
+            options.set("Mapper.num_threads", num_cpus::get() as i64);
+        }
+    }
+    options.apply(&mut mapper_cmd);
+    mapper_cmd
+}
+
+/// Renumbers the sub-models in `sparse/` by the number of registered images, so
+/// `sparse/0` is the largest one and the one the later steps use.
+///
+/// # Returns
+///
+/// The number of images registered in each sub-model, largest first.
+fn rank_sub_models(scene: &Scene) -> Result<Vec<usize>> {
+    let dirs = colmap::sub_model_dirs(&scene.sparse_dir)?;
+    let mut models = Vec::with_capacity(dirs.len());
+    for dir in dirs {
+        let registered = colmap::read_model(&dir)?.images.len();
+        models.push((dir, registered));
+    }
+    // Sorting is stable, so sub-models of equal size keep their order.
+    models.sort_by_key(|&(_, registered)| std::cmp::Reverse(registered));
+
+    // Move the models aside first, as the new numbers may still be taken.
+    let mut moved = Vec::with_capacity(models.len());
+    for (i, (dir, _)) in models.iter().enumerate() {
+        let temporary = scene.sparse_dir.join(format!("ranked-{}", i));
+        fs::rename(dir, &temporary)?;
+        moved.push(temporary);
+    }
+    for (i, temporary) in moved.iter().enumerate() {
+        fs::rename(temporary, scene.sparse_dir.join(i.to_string()))?;
+    }
+    Ok(models
+        .into_iter()
+        .map(|(_, registered)| registered)
+        .collect())
+}
+
+/// Merges the other sub-models into `sparse/0` with `colmap model_merger`.
+///
+/// Sub-models are merged from the largest to the smallest. `model_merger` needs
+/// images registered in both models to align them, so a sub-model that cannot be
+/// merged is kept and a warning is printed.
+fn merge_sub_models(scene: &Scene, tools: &Toolchain) -> Result<()> {
+    let dirs = colmap::sub_model_dirs(&scene.sparse_dir)?;
+    if dirs.len() < 2 {
+        return Ok(());
+    }
+    let main_dir = &dirs[0];
+    let merged_dir = scene.sparse_dir.join("merged");
+    let mut unmerged = 0;
+    for dir in &dirs[1..] {
+        reset_dir(&merged_dir)?;
+        let mut merger_cmd = tools.command(tools.colmap()?);
+        merger_cmd
+            .arg("model_merger")
+            .arg("--input_path1")
+            .arg(main_dir)
+            .arg("--input_path2")
+            .arg(dir)
+            .arg("--output_path")
+            .arg(&merged_dir);
+        let merged = run_tool(scene, Step::Mapping, &mut merger_cmd, "model_merger").is_ok()
+            && (colmap::binary::exists(&merged_dir) || colmap::text::exists(&merged_dir));
+        if merged {
+            fs::remove_dir_all(main_dir)?;
+            fs::rename(&merged_dir, main_dir)?;
+            fs::remove_dir_all(dir)?;
+        } else {
+            unmerged += 1;
+        }
+    }
+    if merged_dir.exists() {
+        fs::remove_dir_all(&merged_dir)?;
+    }
+
+    let registered = colmap::read_model(main_dir)?.images.len();
+    scene.job.println(format!(
+        "[INFO] Merged {} of {} sub-models into sparse/0 ({} registered images).",
+        dirs.len() - 1 - unmerged,
+        dirs.len() - 1,
+        registered
+    ));
+    if unmerged > 0 {
+        scene.job.warn(&format!(
+            "{} sub-model(s) could not be merged and were kept in sparse/",
+            unmerged
+        ));
+        rank_sub_models(scene)?;
+    }
+    Ok(())
+}
+
+/// 5. Undistort the images and model into the layout Gaussian splatting expects.
+fn undistort_images(scene: &Scene, tools: &Toolchain) -> Result<()> {
+    let model_path = scene.sparse_dir.join("0");
+    if !model_path.exists() {
+        scene
+            .job
+            .println("[INFO] No model was reconstructed - nothing to undistort.");
+        return Ok(());
+    }
+
+    let images_dir = scene.model_images_dir();
+    let output_sparse_dir = scene.dir.join("sparse");
+    for dir in [&images_dir, &output_sparse_dir] {
+        if dir.exists() {
+            fs::remove_dir_all(dir)?;
+        }
+    }
+
+    let mut undistorter_cmd = undistorter_command(scene, tools, &scene.dir)?;
+    run_tool(
+        scene,
+        Step::Undistort,
+        &mut undistorter_cmd,
+        "image_undistorter",
+    )?;
+
+    // The undistorter writes the model straight into sparse/, trainers read sparse/0.
+    let model_dir = scene.model_dir();
+    fs::create_dir_all(&model_dir)?;
+    for entry in fs::read_dir(&output_sparse_dir)? {
+        let path = entry?.path();
+        if path.is_file() {
+            fs::rename(&path, model_dir.join(path.file_name().unwrap()))?;
+        }
+    }
+    // Trainers only read the binary model, which some COLMAP versions do not write.
+    if !colmap::binary::exists(&model_dir) {
+        let model = colmap::text::read_model(&model_dir)?;
+        colmap::binary::write_model(&model_dir, &model)?;
+    }
+
+    // Drop the dense stereo workspace and scripts the undistorter also creates.
+    let stereo_dir = scene.dir.join("stereo");
+    if stereo_dir.exists() {
+        fs::remove_dir_all(&stereo_dir)?;
+    }
+    for entry in fs::read_dir(&scene.dir)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap().to_string_lossy();
+        if path.is_file() && name.starts_with("run-colmap-") && name.ends_with(".sh") {
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// 6. Undistort the images into the dense workspace `dense/`.
+fn prepare_dense_workspace(scene: &Scene, tools: &Toolchain) -> Result<()> {
+    let model_path = scene.sparse_dir.join("0");
+    if !model_path.exists() {
+        scene
+            .job
+            .println("[INFO] No model was reconstructed - skipping dense reconstruction.");
+        return Ok(());
+    }
+    reset_dir(&scene.dense_dir)?;
+
+    let mut undistorter_cmd = undistorter_command(scene, tools, &scene.dense_dir)?;
+    run_tool(
+        scene,
+        Step::DenseUndistort,
+        &mut undistorter_cmd,
+        "image_undistorter",
+    )
+}
+
+/// Builds the `image_undistorter` command undistorting `sparse/0` and its images
+/// into `output_dir`.
+fn undistorter_command(scene: &Scene, tools: &Toolchain, output_dir: &Path) -> Result<Command> {
+    let mut undistorter_cmd = tools.command(tools.colmap()?);
+    undistorter_cmd
+        .arg("image_undistorter")
+        .arg("--image_path")
+        .arg(&scene.images_dir)
+        .arg("--input_path")
+        .arg(scene.sparse_dir.join("0"))
+        .arg("--output_path")
+        .arg(output_dir)
+        .arg("--output_type")
+        .arg("COLMAP");
+    scene.config.image_undistorter.apply(&mut undistorter_cmd);
+    Ok(undistorter_cmd)
+}
+
+/// Returns whether the dense workspace was prepared, printing a note if not.
+fn has_dense_workspace(scene: &Scene) -> bool {
+    let exists = scene.dense_dir.join("sparse").exists();
+    if !exists {
+        scene.job.println("[INFO] No dense workspace - skipping.");
+    }
+    exists
+}
+
+/// 7. Compute a depth and normal map for every undistorted image.
+fn run_patch_match_stereo(scene: &Scene, tools: &Toolchain) -> Result<()> {
+    if !has_dense_workspace(scene) {
+        return Ok(());
+    }
+    let mut stereo_cmd = stereo_command(scene, tools)?;
+    run_tool(scene, Step::Stereo, &mut stereo_cmd, "patch_match_stereo")
+}
+
+fn stereo_command(scene: &Scene, tools: &Toolchain) -> Result<Command> {
+    let mut stereo_cmd = tools.command(tools.colmap()?);
+    stereo_cmd
+        .arg("patch_match_stereo")
+        .arg("--workspace_path")
+        .arg(&scene.dense_dir)
+        .arg("--workspace_format")
+        .arg("COLMAP");
+    scene.config.patch_match_stereo.apply(&mut stereo_cmd);
+    Ok(stereo_cmd)
+}
+
+/// 8. Fuse the depth maps into the dense point cloud `dense/fused.ply`.
+fn run_stereo_fusion(scene: &Scene, tools: &Toolchain) -> Result<()> {
+    if !has_dense_workspace(scene) {
+        return Ok(());
+    }
+    let mut fusion_cmd = fusion_command(scene, tools)?;
+    run_tool(scene, Step::Fusion, &mut fusion_cmd, "stereo_fusion")
+}
+
+fn fusion_command(scene: &Scene, tools: &Toolchain) -> Result<Command> {
+    let mut fusion_cmd = tools.command(tools.colmap()?);
+    fusion_cmd
+        .arg("stereo_fusion")
+        .arg("--workspace_path")
+        .arg(&scene.dense_dir)
+        .arg("--workspace_format")
+        .arg("COLMAP")
+        .arg("--output_path")
+        .arg(scene.dense_dir.join("fused.ply"));
+    scene.config.stereo_fusion.apply(&mut fusion_cmd);
+    Ok(fusion_cmd)
+}
+
+/// 9. Mesh the dense point cloud with the selected mesher.
+fn run_mesher(scene: &Scene, tools: &Toolchain) -> Result<()> {
+    let Some(mesher) = scene.config.dense.mesher else {
+        return Ok(());
+    };
+    if !has_dense_workspace(scene) {
+        return Ok(());
+    }
+    let mut mesher_cmd = mesher_command(scene, tools, mesher)?;
+    run_tool(scene, Step::Meshing, &mut mesher_cmd, mesher.command())
+}
+
+fn mesher_command(scene: &Scene, tools: &Toolchain, mesher: Mesher) -> Result<Command> {
+    let mut mesher_cmd = tools.command(tools.colmap()?);
+    mesher_cmd.arg(mesher.command());
+    match mesher {
+        // The Poisson mesher reads the fused points, the Delaunay mesher the
+        // whole workspace including the visibility of every point.
+        Mesher::Poisson => mesher_cmd
+            .arg("--input_path")
+            .arg(scene.dense_dir.join("fused.ply")),
+        Mesher::Delaunay => mesher_cmd.arg("--input_path").arg(&scene.dense_dir),
+    };
+    mesher_cmd
+        .arg("--output_path")
+        .arg(scene.dense_dir.join(mesher.output_file()));
+    let options = match mesher {
+        Mesher::Poisson => &scene.config.poisson_mesher,
+        Mesher::Delaunay => &scene.config.delaunay_mesher,
+    };
+    options.apply(&mut mesher_cmd);
+    Ok(mesher_cmd)
+}
+
+/// Export the reconstructed model to a human-readable TXT format, and to every
+/// format selected with `--export`.
+///
+/// With the Gaussian splatting layout the undistorted model is exported, and no
+/// TXT files are added next to `sparse/0`. With `--sub-models all` every other
+/// sub-model is exported into `sub_models/<n>/` as well.
+fn export_model(scene: &Scene, args: &Args) -> Result<()> {
+    let model_path = scene.model_dir();
+    if !model_path.exists() {
+        scene
+            .job
+            .println("[INFO] No model was reconstructed - nothing to export.");
+        return Ok(());
+    }
+
+    if scene.sources.len() > 1 && args.export.contains(&ExportFormat::Blender) {
+        scene.job.warn(
+            "the Blender camera track of a merged scene numbers the frames of every input from 1",
+        );
+    }
+    let txt_dir = (scene.layout == Layout::Colmap).then_some(scene.sparse_dir.as_path());
+    let images_dir = scene.model_images_dir();
+    export_sub_model(
+        scene,
+        args,
+        &model_path,
+        txt_dir,
+        &scene.dir,
+        &images_dir,
+        scene.name,
+    )?;
+
+    let sub_models_dir = scene.dir.join("sub_models");
+    if sub_models_dir.exists() {
+        fs::remove_dir_all(&sub_models_dir)?;
+    }
+    if args.sub_models != SubModels::All {
+        return Ok(());
+    }
+    for dir in colmap::sub_model_dirs(&scene.sparse_dir)?.iter().skip(1) {
+        let index = dir.file_name().unwrap().to_string_lossy();
+        let output_dir = sub_models_dir.join(index.as_ref());
+        fs::create_dir_all(&output_dir)?;
+        scene.job.println(format!(
+            "[INFO] Exporting sub-model {} to {}",
+            index,
+            output_dir.display()
+        ));
+        let name = format!("{}_{}", scene.name, index);
+        // Reach the frames through the scene directory, so paths in exported
+        // files stay relative to `sub_models/<n>/`.
+        let images_dir = Path::new("..")
+            .join("..")
+            .join(scene.images_dir.strip_prefix(&scene.dir)?);
+        let images_dir = output_dir.join(images_dir);
+        export_sub_model(
+            scene,
+            args,
+            dir,
+            Some(&output_dir),
+            &output_dir,
+            &images_dir,
+            &name,
+        )?;
+    }
+    Ok(())
+}
+
+/// Exports a single model to TXT and to every format selected with `--export`.
+///
+/// # Arguments
+///
+/// * `scene` - The scene the model belongs to.
+/// * `args` - The command-line arguments.
+/// * `model_path` - Directory of the model.
+/// * `txt_dir` - Where to write the TXT version of the model, if anywhere.
+/// * `output_dir` - Where the exporters write their files.
+/// * `images_dir` - Directory of the images the model refers to.
+/// * `name` - Name of the exported objects.
+fn export_sub_model(
+    scene: &Scene,
+    args: &Args,
+    model_path: &Path,
+    txt_dir: Option<&Path>,
+    output_dir: &Path,
+    images_dir: &Path,
+    name: &str,
+) -> Result<()> {
+    let model = colmap::read_model(model_path)?;
+    model.validate()?;
+    if let Some(txt_dir) = txt_dir {
+        colmap::text::write_model(txt_dir, &model)?;
+    }
+    scene.job.println(format!(
+        "[INFO] Model has {} camera(s), {} registered images and {} 3D points.",
+        model.cameras.len(),
+        model.images.len(),
+        model.points3d.len()
+    ));
+
+    let ctx = ExportContext {
+        scene_name: name,
+        scene_dir: output_dir,
+        images_dir,
+        job: scene.job,
+        frame_step: scene.config.extract.frame_step(),
+    };
+    for &format in &args.export {
+        export::export(format, &model, &ctx)?;
+    }
+    Ok(())
+}
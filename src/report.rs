@@ -0,0 +1,108 @@
+//! Reconstruction quality report written after every scene.
+//!
+//! The mapper can "succeed" while registering only a handful of frames, so every
+//! scene gets a `report.json` summarizing how much of the video was reconstructed.
+
+use crate::colmap::{self, Reconstruction};
+use crate::Tool;
+use anyhow::Result;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+/// File name of the report inside a scene directory.
+pub const REPORT_FILE: &str = "report.json";
+
+/// Summary of a scene's reconstruction.
+#[derive(Serialize, Debug, Clone)]
+pub struct SceneReport {
+    /// Name of the scene.
+    pub scene: String,
+    /// Number of images fed to the feature extractor.
+    pub extracted_images: usize,
+    /// Number of images registered in the exported model.
+    pub registered_images: usize,
+    /// `registered_images / extracted_images`.
+    pub registration_ratio: f64,
+    /// Number of triangulated 3D points.
+    pub points3d: usize,
+    /// Mean number of images observing a 3D point.
+    pub mean_track_length: f64,
+    /// Mean reprojection error in pixels.
+    pub mean_reprojection_error: f64,
+    /// Number of sub-models the mapper created in `sparse/`.
+    pub models: usize,
+    /// The tool whose mapper produced the model, if known.
+    pub mapper: Option<Tool>,
+}
+
+impl SceneReport {
+    /// Builds the report from a scene's images and its exported model, if any.
+    pub fn new(
+        scene: &str,
+        images_dir: &Path,
+        sparse_dir: &Path,
+        model: Option<&Reconstruction>,
+    ) -> Result<Self> {
+        let extracted_images = count_files(images_dir)?;
+        let empty = Reconstruction::default();
+        let model = model.unwrap_or(&empty);
+        let registered_images = model.images.len();
+        Ok(SceneReport {
+            scene: scene.to_string(),
+            extracted_images,
+            registered_images,
+            registration_ratio: if extracted_images == 0 {
+                0.0
+            } else {
+                registered_images as f64 / extracted_images as f64
+            },
+            points3d: model.points3d.len(),
+            mean_track_length: model.mean_track_length(),
+            mean_reprojection_error: model.mean_reprojection_error(),
+            models: colmap::sub_model_dirs(sparse_dir)?.len(),
+            mapper: None,
+        })
+    }
+
+    /// Writes the report as `report.json` into the scene directory.
+    pub fn save(&self, scene_dir: &Path) -> Result<()> {
+        fs::write(
+            scene_dir.join(REPORT_FILE),
+            serde_json::to_string_pretty(self)?,
+        )?;
+        Ok(())
+    }
+
+    /// Returns a short human-readable summary.
+    pub fn summary(&self) -> String {
+        format!(
+            "[INFO] Registered {} of {} images ({:.1}%), {} 3D points, mean track length {:.2}, mean reprojection error {:.3}px, {} model(s).",
+            self.registered_images,
+            self.extracted_images,
+            self.registration_ratio * 100.0,
+            self.points3d,
+            self.mean_track_length,
+            self.mean_reprojection_error,
+            self.models
+        )
+    }
+}
+
+/// Counts the files in `dir` and its subfolders, which hold the frames of each
+/// input of a merged scene.
+fn count_files(dir: &Path) -> Result<usize> {
+    if !dir.exists() {
+        return Ok(0);
+    }
+    let mut count = 0;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            count += count_files(&path)?;
+        } else if path.is_file() {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
@@ -0,0 +1,378 @@
+//! Runs the pipelines of several scenes at once.
+//!
+//! With `--jobs 1` videos are processed one after another and their output is
+//! printed as it happens. With more jobs, every video gets its own progress bar and
+//! its messages are printed above the bars. Steps that saturate a shared resource
+//! only run for one video at a time: feature extraction, matching and dense stereo
+//! share the GPU, while the mapper, stereo fusion and meshing use every CPU core.
+
+use crate::input::SceneInput;
+use crate::output::{self, say, Event};
+use crate::state::Step;
+use anyhow::Result;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::cell::{Cell, OnceCell};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::Instant;
+
+/// A resource that only one pipeline step may use at a time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Resource {
+    Gpu,
+    Cpu,
+}
+
+impl Resource {
+    /// Returns the resource a step saturates, if any.
+    fn of(step: Step) -> Option<Resource> {
+        match step {
+            Step::Features | Step::Matching | Step::Stereo => Some(Resource::Gpu),
+            Step::Mapping | Step::Fusion | Step::Meshing => Some(Resource::Cpu),
+            _ => None,
+        }
+    }
+}
+
+/// Distributes videos over worker threads and limits concurrent steps.
+pub struct Scheduler {
+    jobs: usize,
+    progress: Option<MultiProgress>,
+    gpu: Mutex<()>,
+    cpu: Mutex<()>,
+    /// Whether scenes are only planned, not processed.
+    planning: bool,
+}
+
+impl Scheduler {
+    /// Creates a scheduler running up to `jobs` videos at once.
+    pub fn new(jobs: usize) -> Self {
+        Scheduler {
+            jobs: jobs.max(1),
+            progress: (jobs > 1).then(MultiProgress::new),
+            gpu: Mutex::new(()),
+            cpu: Mutex::new(()),
+            planning: false,
+        }
+    }
+
+    /// Creates a scheduler for `--dry-run`, which plans one scene after another.
+    /// Planned scenes are not reported as started or finished, so wrappers do not
+    /// take them for processed scenes.
+    pub fn planner() -> Self {
+        Scheduler {
+            planning: true,
+            ..Scheduler::new(1)
+        }
+    }
+
+    /// Processes every scene with `process`, printing an error for each scene that
+    /// fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `scenes` - The scenes to process.
+    /// * `process` - Runs the pipeline of a single scene.
+    ///
+    /// # Returns
+    ///
+    /// The number of scenes that failed.
+    pub fn run<F>(&self, scenes: &[SceneInput], process: F) -> usize
+    where
+        F: Fn(&SceneInput, &Job) -> Result<()> + Sync,
+    {
+        let next = AtomicUsize::new(0);
+        let failed = AtomicUsize::new(0);
+        let worker = || loop {
+            let index = next.fetch_add(1, Ordering::SeqCst);
+            let Some(scene) = scenes.get(index) else {
+                break;
+            };
+            let job = self.job(scene);
+            match process(scene, &job) {
+                Ok(()) => job.finish(),
+                Err(e) => {
+                    job.fail(&format!("Failed to process {}: {}", scene.describe(), e));
+                    failed.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+        };
+
+        let workers = self.jobs.min(scenes.len());
+        if workers <= 1 {
+            worker();
+        } else {
+            thread::scope(|scope| {
+                for _ in 0..workers {
+                    scope.spawn(worker);
+                }
+            });
+        }
+        failed.into_inner()
+    }
+
+    fn job(&self, scene: &SceneInput) -> Job<'_> {
+        let name = scene.name.clone();
+        let bar = self.progress.as_ref().map(|progress| {
+            let bar = progress.add(ProgressBar::new(0));
+            bar.set_style(
+                ProgressStyle::with_template(
+                    "{prefix:>16.bold} [{bar:20.cyan/blue}] {pos}/{len} {wide_msg}",
+                )
+                .unwrap()
+                .progress_chars("#>-"),
+            );
+            bar.set_prefix(name.clone());
+            bar.set_message("waiting");
+            bar
+        });
+        if bar.is_none() {
+            say!("\n=== Processing {} ===", name);
+        }
+        if !self.planning {
+            output::emit(&Event::SceneStarted {
+                scene: &name,
+                inputs: &scene.sources,
+            });
+        }
+        Job {
+            name,
+            bar,
+            scheduler: self,
+            started: Instant::now(),
+            step_started: Cell::new(None),
+        }
+    }
+}
+
+/// The processing of a single video, scheduled by a [`Scheduler`].
+pub struct Job<'a> {
+    name: String,
+    bar: Option<ProgressBar>,
+    scheduler: &'a Scheduler,
+    started: Instant,
+    step_started: Cell<Option<Instant>>,
+}
+
+impl Job<'_> {
+    /// Returns the name of the scene being processed.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Prints a message of this video. With several jobs the message is printed
+    /// above the progress bars and prefixed with the video name.
+    pub fn println(&self, message: impl AsRef<str>) {
+        match &self.bar {
+            Some(_) => self.print_above(|| say!("{}: {}", self.name, message.as_ref())),
+            None => say!("{}", message.as_ref()),
+        }
+    }
+
+    /// Prints a warning about this video and emits it as a warning event.
+    pub fn warn(&self, message: &str) {
+        match &self.bar {
+            Some(_) => self.print_above(|| say!("{}: [WARN] {}", self.name, message)),
+            None => say!("[WARN] {}: {}", self.name, message),
+        }
+        output::emit(&Event::Warning {
+            scene: Some(&self.name),
+            message,
+        });
+    }
+
+    /// Reports the start of a pipeline step.
+    pub fn start_step(&self, index: usize, total: usize, step: Step) {
+        match &self.bar {
+            Some(bar) => {
+                bar.set_length(total as u64);
+                bar.set_position(index as u64);
+                bar.set_message(step.label());
+            }
+            None => say!("[{}/{}] {}...", index + 1, total, step.label()),
+        }
+        self.step_started.set(Some(Instant::now()));
+        output::emit(&Event::StepStarted {
+            scene: &self.name,
+            step,
+            index: index + 1,
+            total,
+        });
+    }
+
+    /// Reports that the pipeline step started last finished successfully.
+    pub fn finish_step(&self, step: Step) {
+        let duration = self
+            .step_started
+            .take()
+            .map_or(0.0, |started| started.elapsed().as_secs_f64());
+        output::emit(&Event::StepFinished {
+            scene: &self.name,
+            step,
+            duration_secs: duration,
+        });
+    }
+
+    /// Waits until no other video uses the resource `step` needs. The step may run
+    /// while the returned guard is alive.
+    pub fn acquire(&self, step: Step) -> Option<MutexGuard<'_, ()>> {
+        let lock = match Resource::of(step)? {
+            Resource::Gpu => &self.scheduler.gpu,
+            Resource::Cpu => &self.scheduler.cpu,
+        };
+        if let Some(bar) = &self.bar {
+            bar.set_message(format!("{} (waiting)", step.label()));
+        }
+        let guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(bar) = &self.bar {
+            bar.set_message(step.label());
+        }
+        Some(guard)
+    }
+
+    /// Creates the progress display of an external tool run by the current step.
+    pub fn tool_progress(&self, tool: &str) -> ToolProgress<'_> {
+        ToolProgress {
+            job: self,
+            tool: tool.to_string(),
+            bar: OnceCell::new(),
+        }
+    }
+
+    fn finish(&self) {
+        if let Some(bar) = &self.bar {
+            let total = bar.length().unwrap_or(0);
+            bar.set_position(total);
+            bar.finish_with_message("✔ done");
+        }
+        self.emit_finished(true);
+    }
+
+    fn fail(&self, message: &str) {
+        match &self.bar {
+            Some(bar) => {
+                self.print_above(|| output::print_error(format_args!("[ERROR] {}", message)));
+                bar.abandon_with_message("✘ failed");
+            }
+            None => output::print_error(format_args!("[ERROR] {}", message)),
+        }
+        output::emit(&Event::Error {
+            scene: Some(&self.name),
+            message,
+        });
+        self.emit_finished(false);
+    }
+
+    /// Prints with the progress bars cleared. `ProgressBar::println` is not used
+    /// because it drops the line when the bars are hidden, which they are whenever
+    /// stderr is not a terminal.
+    fn print_above(&self, print: impl FnOnce()) {
+        match &self.scheduler.progress {
+            Some(progress) => progress.suspend(print),
+            None => print(),
+        }
+    }
+
+    fn emit_finished(&self, success: bool) {
+        if self.scheduler.planning {
+            return;
+        }
+        output::emit(&Event::SceneFinished {
+            scene: &self.name,
+            success,
+            duration_secs: self.started.elapsed().as_secs_f64(),
+        });
+    }
+}
+
+/// Progress of an external tool, driven by the counters it prints.
+///
+/// With a single job a progress bar is shown below the step while the tool runs.
+/// With several jobs the counter is shown on the progress bar of the video.
+pub struct ToolProgress<'a> {
+    job: &'a Job<'a>,
+    tool: String,
+    bar: OnceCell<ProgressBar>,
+}
+
+impl ToolProgress<'_> {
+    /// Reports that `done` of `total` items were processed. Without a total only
+    /// the count is shown.
+    pub fn update(&self, done: u64, total: Option<u64>) {
+        if let Some(job_bar) = &self.job.bar {
+            let count = match total {
+                Some(total) => format!("{}/{}", done, total),
+                None => done.to_string(),
+            };
+            job_bar.set_message(format!("{} ({})", self.tool, count));
+            return;
+        }
+
+        let bar = self.bar.get_or_init(|| {
+            let bar = ProgressBar::new_spinner();
+            bar.set_message(self.tool.clone());
+            bar
+        });
+        if let Some(total) = total {
+            if bar.length() != Some(total) {
+                bar.set_style(
+                    ProgressStyle::with_template(
+                        "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} {msg}",
+                    )
+                    .unwrap()
+                    .progress_chars("#>-"),
+                );
+                bar.set_length(total);
+            }
+        }
+        bar.set_position(done);
+    }
+}
+
+impl Drop for ToolProgress<'_> {
+    fn drop(&mut self) {
+        if let Some(bar) = self.bar.get() {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indicatif::ProgressDrawTarget;
+    use std::path::PathBuf;
+
+    #[test]
+    fn messages_are_printed_when_progress_bars_are_hidden() {
+        let scheduler = Scheduler {
+            progress: Some(MultiProgress::with_draw_target(ProgressDrawTarget::hidden())),
+            ..Scheduler::new(2)
+        };
+        let scenes = [SceneInput {
+            name: "hidden".to_string(),
+            sources: vec![PathBuf::from("hidden.mp4")],
+            merged: false,
+        }];
+
+        let mut failed = 0;
+        let lines = output::capture(|| {
+            failed = scheduler.run(&scenes, |_, job| {
+                job.println("[INFO] extracting");
+                job.warn("blurry frames");
+                anyhow::bail!("mapper crashed, see hidden/logs/mapping.log")
+            });
+        });
+
+        assert_eq!(failed, 1);
+        assert_eq!(
+            lines,
+            [
+                "hidden: [INFO] extracting",
+                "hidden: [WARN] blurry frames",
+                "[ERROR] Failed to process hidden.mp4: mapper crashed, see hidden/logs/mapping.log",
+            ]
+        );
+    }
+}
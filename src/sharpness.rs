@@ -0,0 +1,249 @@
+//! Sharpness-based keyframe selection.
+//!
+//! Motion-blurred frames are the main reason the mapper fails to register images.
+//! After frame extraction every `frame_%06d.jpg` is scored by the variance of its
+//! Laplacian. A window of consecutive frames slides over the sequence, and only the
+//! frames that are the sharpest of some window are kept for reconstruction.
+
+use anyhow::{anyhow, Context, Result};
+use image::GrayImage;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+/// Sharpness score of a single extracted frame.
+#[derive(Serialize, Debug, Clone)]
+pub struct FrameScore {
+    /// File name of the frame inside the images directory.
+    pub file: String,
+    /// Variance of the Laplacian of the frame's luma. Higher is sharper.
+    pub score: f64,
+    /// Whether the frame was kept for reconstruction.
+    pub kept: bool,
+}
+
+/// Result of a keyframe selection pass, written to `keyframes.json`.
+#[derive(Serialize, Debug)]
+pub struct KeyframeReport {
+    /// Number of consecutive frames competing for one keyframe.
+    pub window: usize,
+    /// Number of frames that were kept.
+    pub kept: usize,
+    /// Number of frames that were moved out of the images directory.
+    pub dropped: usize,
+    /// Directory the dropped frames were moved to.
+    pub dropped_dir: PathBuf,
+    /// Per-frame scores in frame order.
+    pub frames: Vec<FrameScore>,
+}
+
+/// Computes the variance of the 4-neighbour Laplacian of a grayscale image.
+pub fn laplacian_variance(image: &GrayImage) -> f64 {
+    let (width, height) = image.dimensions();
+    if width < 3 || height < 3 {
+        return 0.0;
+    }
+
+    let pixels = image.as_raw();
+    let stride = width as usize;
+    let mut sum = 0.0;
+    let mut sum_sq = 0.0;
+
+    for y in 1..height as usize - 1 {
+        for x in 1..stride - 1 {
+            let i = y * stride + x;
+            let laplacian = pixels[i - stride] as i32
+                + pixels[i + stride] as i32
+                + pixels[i - 1] as i32
+                + pixels[i + 1] as i32
+                - 4 * pixels[i] as i32;
+            let value = laplacian as f64;
+            sum += value;
+            sum_sq += value * value;
+        }
+    }
+
+    let count = ((width - 2) as f64) * ((height - 2) as f64);
+    let mean = sum / count;
+    sum_sq / count - mean * mean
+}
+
+fn score_frame(path: &Path) -> Result<f64> {
+    let image = image::open(path)
+        .with_context(|| format!("Failed to decode {}", path.display()))?
+        .into_luma8();
+    Ok(laplacian_variance(&image))
+}
+
+/// Lists the extracted `frame_*.jpg` files of a directory in frame order.
+pub fn list_frames(images_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut frames: Vec<PathBuf> = fs::read_dir(images_dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("frame_") && name.ends_with(".jpg"))
+        })
+        .collect();
+    frames.sort();
+    Ok(frames)
+}
+
+/// Scores all frames, spreading the work across the available CPU cores.
+fn score_frames(frames: &[PathBuf]) -> Result<Vec<f64>> {
+    let chunk_size = frames.len().div_ceil(num_cpus::get()).max(1);
+    thread::scope(|scope| {
+        let handles: Vec<_> = frames
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(|f| score_frame(f)).collect()))
+            .collect();
+
+        let mut scores = Vec::with_capacity(frames.len());
+        for handle in handles {
+            let chunk_scores: Result<Vec<f64>> = handle
+                .join()
+                .map_err(|_| anyhow!("Sharpness scoring thread panicked"))?;
+            scores.extend(chunk_scores?);
+        }
+        Ok(scores)
+    })
+}
+
+/// Returns for every frame whether it is the sharpest of some window of `window`
+/// consecutive frames. With fewer frames than the window, all of them form a
+/// single window.
+fn sharpest_in_windows(scores: &[f64], window: usize) -> Vec<bool> {
+    let mut kept = vec![false; scores.len()];
+    let width = window.min(scores.len()).max(1);
+    for (start, window_scores) in scores.windows(width).enumerate() {
+        let best = (0..width)
+            .max_by(|&a, &b| window_scores[a].total_cmp(&window_scores[b]))
+            .unwrap();
+        kept[start + best] = true;
+    }
+    kept
+}
+
+/// Keeps the sharpest frame of every sliding window of `window` consecutive
+/// frames. A frame is dropped only if each window containing it has a sharper
+/// frame, so no `window` consecutive frames are ever dropped together.
+///
+/// # Arguments
+///
+/// * `images_dir` - The directory containing the extracted frames.
+/// * `dropped_dir` - The directory the rejected frames are moved to. Frames
+///   dropped by an earlier pass are moved back first, so a rerun selects from
+///   every extracted frame again.
+/// * `window` - The number of consecutive frames competing for one keyframe.
+///
+/// # Returns
+///
+/// A `KeyframeReport` describing which frames were kept and dropped.
+pub fn select_keyframes(
+    images_dir: &Path,
+    dropped_dir: &Path,
+    window: usize,
+) -> Result<KeyframeReport> {
+    let window = window.max(1);
+    if dropped_dir.exists() {
+        for path in list_frames(dropped_dir)? {
+            let name = path.file_name().unwrap();
+            fs::rename(&path, images_dir.join(name))
+                .with_context(|| format!("Failed to restore dropped frame {}", path.display()))?;
+        }
+    }
+    let frames = list_frames(images_dir)?;
+    let scores = score_frames(&frames)?;
+
+    let mut report_frames: Vec<FrameScore> = frames
+        .iter()
+        .zip(&scores)
+        .map(|(path, &score)| FrameScore {
+            file: path.file_name().unwrap().to_string_lossy().into_owned(),
+            score,
+            kept: false,
+        })
+        .collect();
+
+    for (frame, kept) in report_frames
+        .iter_mut()
+        .zip(sharpest_in_windows(&scores, window))
+    {
+        frame.kept = kept;
+    }
+
+    fs::create_dir_all(dropped_dir)?;
+    for frame in report_frames.iter().filter(|f| !f.kept) {
+        fs::rename(images_dir.join(&frame.file), dropped_dir.join(&frame.file))
+            .with_context(|| format!("Failed to move dropped frame {}", frame.file))?;
+    }
+
+    let kept = report_frames.iter().filter(|f| f.kept).count();
+    Ok(KeyframeReport {
+        window,
+        kept,
+        dropped: report_frames.len() - kept,
+        dropped_dir: dropped_dir.to_path_buf(),
+        frames: report_frames,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_sharpest_frame_of_every_sliding_window() {
+        let scores = [5.0, 1.0, 2.0, 3.0, 9.0, 1.0, 1.0];
+        // Windows of 3: [5 1 2] [1 2 3] [2 3 9] [3 9 1] [9 1 1].
+        assert_eq!(
+            sharpest_in_windows(&scores, 3),
+            [true, false, false, true, true, false, false]
+        );
+        assert_eq!(sharpest_in_windows(&scores[..2], 3), [true, false]);
+        assert!(sharpest_in_windows(&[], 3).is_empty());
+    }
+
+    #[test]
+    fn rerunning_the_selection_keeps_the_same_frames() {
+        let dir =
+            std::env::temp_dir().join(format!("polyfjord3d-keyframes-{}", std::process::id()));
+        let images_dir = dir.join("images");
+        let dropped_dir = dir.join("dropped_frames");
+        fs::create_dir_all(&images_dir).unwrap();
+        // A checkerboard whose contrast varies from frame to frame.
+        for (i, contrast) in [40u8, 10, 90, 20, 30, 70, 5, 60, 15, 80, 25, 50]
+            .into_iter()
+            .enumerate()
+        {
+            let image = GrayImage::from_fn(16, 16, |x, y| {
+                image::Luma([if (x + y) % 2 == 0 {
+                    100 + contrast
+                } else {
+                    100
+                }])
+            });
+            image
+                .save(images_dir.join(format!("frame_{:06}.jpg", i + 1)))
+                .unwrap();
+        }
+
+        let kept = |report: &KeyframeReport| -> Vec<String> {
+            report
+                .frames
+                .iter()
+                .filter(|f| f.kept)
+                .map(|f| f.file.clone())
+                .collect()
+        };
+        let first = select_keyframes(&images_dir, &dropped_dir, 3).unwrap();
+        let second = select_keyframes(&images_dir, &dropped_dir, 3).unwrap();
+        assert!(first.dropped > 0);
+        assert_eq!(second.frames.len(), 12);
+        assert_eq!(kept(&first), kept(&second));
+        assert_eq!(list_frames(&images_dir).unwrap().len(), first.kept);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
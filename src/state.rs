@@ -0,0 +1,162 @@
+//! Tracking of completed pipeline steps for a scene.
+//!
+//! Every step of the pipeline records its completion in `pipeline_state.json` inside
+//! the scene directory, so a failed scene can be resumed from the first step that
+//! did not finish instead of starting over.
+
+use crate::Tool;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File name of the state file inside a scene directory.
+const STATE_FILE: &str = "pipeline_state.json";
+
+/// A step of the reconstruction pipeline, in execution order.
+#[derive(
+    clap::ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Step {
+    /// Extract frames from the video with ffmpeg.
+    Extract,
+    /// Drop blurry frames (only when `--keyframe-window` is set).
+    Keyframes,
+    /// Detect features with COLMAP's feature extractor.
+    Features,
+    /// Match features between images.
+    Matching,
+    /// Run the sparse mapper.
+    Mapping,
+    /// Undistort the images and model (only with `--layout gaussian-splatting`).
+    Undistort,
+    /// Undistort the images into the dense workspace (only with `--dense`).
+    DenseUndistort,
+    /// Compute depth maps with `patch_match_stereo` (only with `--dense`).
+    Stereo,
+    /// Fuse the depth maps into a dense point cloud (only with `--dense`).
+    Fusion,
+    /// Mesh the dense point cloud (only with `--mesher`).
+    Meshing,
+    /// Convert the reconstructed model to TXT and run the selected exporters.
+    Export,
+}
+
+impl Step {
+    /// All steps in execution order.
+    pub const ALL: [Step; 11] = [
+        Step::Extract,
+        Step::Keyframes,
+        Step::Features,
+        Step::Matching,
+        Step::Mapping,
+        Step::Undistort,
+        Step::DenseUndistort,
+        Step::Stereo,
+        Step::Fusion,
+        Step::Meshing,
+        Step::Export,
+    ];
+
+    /// Human-readable description printed when the step starts.
+    pub fn label(self) -> &'static str {
+        match self {
+            Step::Extract => "Extracting frames",
+            Step::Keyframes => "Selecting sharpest frames",
+            Step::Features => "Feature extraction",
+            Step::Matching => "Feature matching",
+            Step::Mapping => "Sparse reconstruction",
+            Step::Undistort => "Undistorting images",
+            Step::DenseUndistort => "Preparing dense workspace",
+            Step::Stereo => "Dense stereo matching",
+            Step::Fusion => "Stereo fusion",
+            Step::Meshing => "Meshing",
+            Step::Export => "Exporting model",
+        }
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use clap::ValueEnum;
+        match self.to_possible_value() {
+            Some(value) => f.write_str(value.get_name()),
+            None => write!(f, "{:?}", self),
+        }
+    }
+}
+
+/// Completed steps of a scene, persisted in the scene directory.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct SceneState {
+    completed: Vec<Step>,
+    /// The tool whose mapper produced the model.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mapper: Option<Tool>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl SceneState {
+    /// Loads the state of a scene, or an empty state if none was recorded yet.
+    pub fn load(scene_dir: &Path) -> Result<Self> {
+        let path = scene_dir.join(STATE_FILE);
+        let mut state = if path.exists() {
+            let contents = fs::read_to_string(&path)?;
+            serde_json::from_str::<SceneState>(&contents)
+                .with_context(|| format!("Failed to parse {}", path.display()))?
+        } else {
+            SceneState::default()
+        };
+        state.path = path;
+        Ok(state)
+    }
+
+    fn save(&self) -> Result<()> {
+        fs::write(&self.path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+
+    /// Returns whether the scene has a state file. Scenes processed by older
+    /// versions have none.
+    pub fn is_recorded(&self) -> bool {
+        self.path.is_file()
+    }
+
+    /// Returns whether a step has completed.
+    pub fn is_complete(&self, step: Step) -> bool {
+        self.completed.contains(&step)
+    }
+
+    /// Forgets a step and every step after it, since their outputs are about to be
+    /// regenerated.
+    pub fn invalidate_from(&mut self, step: Step) -> Result<()> {
+        self.completed.retain(|&s| s < step);
+        if step <= Step::Mapping {
+            self.mapper = None;
+        }
+        self.save()
+    }
+
+    /// Returns the tool whose mapper produced the model, if known.
+    pub fn mapper(&self) -> Option<Tool> {
+        self.mapper
+    }
+
+    /// Records the tool whose mapper produced the model.
+    pub fn set_mapper(&mut self, tool: Tool) -> Result<()> {
+        self.mapper = Some(tool);
+        self.save()
+    }
+
+    /// Records a step as completed.
+    pub fn mark_complete(&mut self, step: Step) -> Result<()> {
+        if !self.is_complete(step) {
+            self.completed.push(step);
+            self.completed.sort();
+        }
+        self.save()
+    }
+}
@@ -0,0 +1,114 @@
+//! Reading and interpreting the output of ffmpeg, COLMAP and GLOMAP while they run.
+
+use std::io::Read;
+use std::sync::mpsc::Sender;
+use std::thread::{self, JoinHandle};
+
+/// The stream a line of output was printed on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Reads `reader` on a background thread and sends every line to `lines`.
+///
+/// Lines end at `\n` or `\r`, since ffmpeg rewrites its `frame=` status line with
+/// carriage returns. Empty lines are skipped and invalid UTF-8 is replaced.
+pub fn spawn_line_reader<R>(
+    mut reader: R,
+    stream: Stream,
+    lines: Sender<(Stream, String)>,
+) -> JoinHandle<()>
+where
+    R: Read + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut buffer = [0u8; 8192];
+        let mut line = Vec::new();
+        let send = |line: &mut Vec<u8>| {
+            if !line.is_empty() {
+                let text = String::from_utf8_lossy(line).into_owned();
+                line.clear();
+                // The receiver only goes away if the command is abandoned.
+                let _ = lines.send((stream, text));
+            }
+        };
+        while let Ok(n) = reader.read(&mut buffer) {
+            if n == 0 {
+                break;
+            }
+            for &byte in &buffer[..n] {
+                if byte == b'\n' || byte == b'\r' {
+                    send(&mut line);
+                } else {
+                    line.push(byte);
+                }
+            }
+        }
+        send(&mut line);
+    })
+}
+
+/// Extracts the progress of a tool from a line of its output.
+///
+/// Recognizes ffmpeg's `frame=  123 fps=...` status lines, COLMAP's
+/// `Processed file [12/345]`, `Matching image [12/345]` and similar `[i/N]`
+/// counters, and `Processing view 12 / 345` from `patch_match_stereo`.
+///
+/// # Returns
+///
+/// The number of processed items and, if the tool printed it, the total.
+pub fn parse_progress(line: &str) -> Option<(u64, Option<u64>)> {
+    let line = line.trim();
+    if let Some(rest) = line.strip_prefix("frame=") {
+        return leading_number(rest.trim_start()).map(|frame| (frame, None));
+    }
+    if let Some(start) = line.find("Processing view ") {
+        let rest = &line[start + "Processing view ".len()..];
+        let (done, rest) = rest.split_once('/')?;
+        let done = done.trim().parse().ok()?;
+        let total = leading_number(rest.trim_start())?;
+        return Some((done, Some(total)));
+    }
+    let rest = &line[line.find('[')? + 1..];
+    let (done, rest) = rest.split_once('/')?;
+    let done = done.parse().ok()?;
+    let total = leading_number(rest)?;
+    Some((done, Some(total)))
+}
+
+/// Parses the digits at the start of `s`.
+fn leading_number(s: &str) -> Option<u64> {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    s[..end].parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_progress_counters_of_the_tools() {
+        assert_eq!(
+            parse_progress("frame=  123 fps= 45 q=2.0 size=N/A"),
+            Some((123, None))
+        );
+        assert_eq!(
+            parse_progress(
+                "I20240101 12:00:00.000 feature_extraction.cc:123] Processed file [12/345]"
+            ),
+            Some((12, Some(345)))
+        );
+        assert_eq!(
+            parse_progress("Matching image [3/40] in 0.123s"),
+            Some((3, Some(40)))
+        );
+        assert_eq!(
+            parse_progress("Processing view 7 / 120 for 00007.jpg"),
+            Some((7, Some(120)))
+        );
+        assert_eq!(parse_progress("Elapsed time: 0.012 [minutes]"), None);
+        assert_eq!(parse_progress("Loading database"), None);
+    }
+}